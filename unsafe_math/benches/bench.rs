@@ -4,7 +4,6 @@
 extern crate test;
 
 use unsafe_math::unsafe_math;
-use unsafe_math::UnsafeMath;
 
 // no_mangle is for cargo asm
 
@@ -49,6 +48,21 @@ fn sliding_sum_fast(data: &[u32], window: usize) -> u64 {
     out
 }
 
+#[unsafe(no_mangle)]
+fn sliding_sum_fast_index(data: &[u32], window: usize) -> u64 {
+    let mut out = 0;
+    // same as sliding_sum_fast, but without bounds checks on data[i + j]
+    #[unsafe_math(index)]
+    for i in 0..data.len() - window {
+        let mut sum = 0;
+        for j in 0..window {
+            sum += data[i + j];
+        }
+        out += sum as u64;
+    }
+    out
+}
+
 #[unsafe(no_mangle)]
 pub fn bilinear_sample_baseline(a00: f64, a10: f64, a01: f64, a11: f64, fx: f64, fy: f64) -> f64 {
     let inv_fx = 1.0 - fx;
@@ -133,6 +147,18 @@ mod tests {
         b.iter(|| black_box(sliding_sum_fast(&data, window)))
     }
 
+    #[bench]
+    fn bench_sliding_sum_fast_index(b: &mut Bencher) {
+        let data: Vec<u32> = (0..VECTOR_SIZE as u32)
+            .map(|x| black_box(x.wrapping_mul(7).wrapping_add(3)))
+            .collect();
+        let window = 128;
+
+        (0..1000).map(|_| black_box(sliding_sum_wrapping(&data, window))).for_each(drop);
+
+        b.iter(|| black_box(sliding_sum_fast_index(&data, window)))
+    }
+
     const A00: f64 = black_box(0.1f64);
     const A10: f64 = black_box(0.9f64);
     const A01: f64 = black_box(0.2f64);
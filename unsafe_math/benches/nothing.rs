@@ -1,6 +1,8 @@
 #![feature(stmt_expr_attributes)]
 #![feature(proc_macro_hygiene)]
 #![feature(test)]
+// same code for slow and fast versions, casts included
+#![allow(clippy::unnecessary_cast)]
 extern crate test;
 
 use unsafe_math::*;
@@ -0,0 +1,11 @@
+//! Sets the same `unsafe_math_nightly` cfg as `unsafe_math_trait`, for tests whose results depend on the backend.
+//! Toolchain is detected there, this only reads the result
+
+fn main() {
+    println!("cargo::rustc-check-cfg=cfg(unsafe_math_nightly)");
+    println!("cargo::rerun-if-changed=build.rs");
+
+    if unsafe_math_trait::__private::NIGHTLY {
+        println!("cargo::rustc-cfg=unsafe_math_nightly");
+    }
+}
@@ -1,15 +1,158 @@
-#![feature(stmt_expr_attributes)]
-#![feature(proc_macro_hygiene)]
-
 //! # unsafe_math main crate
 //!
 //! Re-exports unsafe_math macro and trait (proc_macro crates cant export traits)
 //!
+//! `no_std` without the default `std` feature. Then float `sqrt`, `powi`, `mul_add` and euclid methods
+//! and vek types are left as is, since core does not have them. `alloc` feature keeps unchecked indexing of `Vec` and `String`
+//!
 //! See the project [README](https://github.com/platonvin/unsafe_math/blob/master/README.md) for details
+//!
+//! Unknown operator names in arguments are rejected at compile time:
+//! ```compile_fail
+//! use unsafe_math::unsafe_math;
+//!
+//! #[unsafe_math(add, pow)]
+//! fn square(a: u32) -> u32 {
+//!     a * a
+//! }
+//! ```
+//!
+//! Unary minus on unsigned integers stays an error:
+//! ```compile_fail
+//! use unsafe_math::unsafe_math;
+//!
+//! #[unsafe_math]
+//! fn negate(a: u32) -> u32 {
+//!     -a
+//! }
+//! ```
+//!
+//! Floats have no shifts, so shifting one is still a type error rather than UB:
+//! ```compile_fail
+//! use unsafe_math::unsafe_math;
+//!
+//! #[unsafe_math]
+//! fn shift(a: f32) -> f32 {
+//!     a << 1
+//! }
+//! ```
+//!
+//! Unknown policies are rejected too:
+//! ```compile_fail
+//! use unsafe_math::unsafe_math;
+//!
+//! #[unsafe_math(policy = clamping)]
+//! fn mix(a: u8, b: u8) -> u8 {
+//!     a + b
+//! }
+//! ```
+//!
+//! So are unknown float flags:
+//! ```compile_fail
+//! use unsafe_math::unsafe_math;
+//!
+//! #[unsafe_math(float(reassoc, finite))]
+//! fn sum(a: f32, b: f32) -> f32 {
+//!     a + b
+//! }
+//! ```
+//!
+//! `types(...)` takes primitive numeric types only:
+//! ```compile_fail
+//! use unsafe_math::unsafe_math;
+//!
+//! #[unsafe_math(types(f32, char))]
+//! fn sum(a: f32, b: f32) -> f32 {
+//!     a + b
+//! }
+//! ```
+//!
+//! Wrapping and saturating policies can't be limited to some types:
+//! ```compile_fail
+//! use unsafe_math::unsafe_math;
+//!
+//! #[unsafe_math(int, policy = wrapping)]
+//! fn hash(a: u32, b: u32) -> u32 {
+//!     a * b
+//! }
+//! ```
+//!
+//! `only(...)` knows subscripts and range bounds only:
+//! ```compile_fail
+//! use unsafe_math::unsafe_math;
+//!
+//! #[unsafe_math(only(index, loop_body))]
+//! fn sum(a: u32, b: u32) -> u32 {
+//!     a + b
+//! }
+//! ```
+//!
+//! `assume(...)` conditions go at the top of a fn or a block, so they need one:
+//! ```compile_fail
+//! use unsafe_math::unsafe_math;
+//!
+//! #[unsafe_math(assume(true))]
+//! const LIMIT: u32 = 1 << 12;
+//! ```
+//!
+//! Compound assignment borrows like the original, overloaded one borrows the place before evaluating the right side:
+//! ```compile_fail
+//! use unsafe_math::unsafe_math;
+//!
+//! #[derive(Clone, Copy)]
+//! struct Meters(f32);
+//!
+//! impl core::ops::AddAssign for Meters {
+//!     fn add_assign(&mut self, rhs: Meters) {
+//!         self.0 += rhs.0;
+//!     }
+//! }
+//!
+//! #[unsafe_math]
+//! fn accumulate(lengths: &mut Vec<Meters>) {
+//!     lengths[0] += lengths[1];
+//! }
+//! ```
+//!
+//! `#[derive(UnsafeMath)]` is only for structs:
+//! ```compile_fail
+//! #[derive(Clone, Copy, unsafe_math::UnsafeMath)]
+//! enum Level {
+//!     Low,
+//!     High,
+//! }
+//! ```
+
+#![cfg_attr(not(test), no_std)]
 
+pub use unsafe_math_macro::safe_math;
 pub use unsafe_math_macro::unsafe_math;
 pub use unsafe_math_macro::unsafe_math_block;
+pub use unsafe_math_macro::UnsafeMath;
+pub use unsafe_math_trait::FastAbs;
+pub use unsafe_math_trait::FastAdd;
+pub use unsafe_math_trait::FastCast;
+pub use unsafe_math_trait::FastDiv;
+pub use unsafe_math_trait::FastEuclid;
+pub use unsafe_math_trait::FastFloat;
+pub use unsafe_math_trait::FastMul;
+pub use unsafe_math_trait::FastNeg;
+pub use unsafe_math_trait::FastPow;
+pub use unsafe_math_trait::FastRem;
+pub use unsafe_math_trait::FastShl;
+pub use unsafe_math_trait::FastShr;
+pub use unsafe_math_trait::FastSub;
+pub use unsafe_math_trait::FloatFlags;
+pub use unsafe_math_trait::SaturatingMath;
+pub use unsafe_math_trait::ShiftAmount;
 pub use unsafe_math_trait::UnsafeMath;
+pub use unsafe_math_trait::WrappingMath;
+
+#[doc(hidden)]
+pub use unsafe_math_trait::__private;
+
+// generated code refers to `::unsafe_math`, make it resolve inside this crate too
+extern crate self as unsafe_math;
 
 // TODO: i am actually unsure how should i test it
 // i dont want macro to break anything, but UB does not have to equal wrapping_add / sub, does it?
@@ -18,11 +161,11 @@ pub use unsafe_math_trait::UnsafeMath;
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::ops::{Add, Div, Mul, Rem, Sub};
+    use std::ops::{Add, AddAssign, Div, Mul, Rem, Sub};
 
     #[test]
     fn test_integer_fast_add() {
-        use unsafe_math_trait::UnsafeMath;
+        use unsafe_math_trait::FastAdd;
 
         let a: u32 = 1;
         let b: u32 = 2;
@@ -31,8 +174,9 @@ mod tests {
     }
 
     #[test]
+    #[cfg_attr(not(unsafe_math_nightly), ignore = "overflow is UB, only the nightly intrinsics wrap")]
     fn test_integer_overflow_behaviour() {
-        use unsafe_math_trait::UnsafeMath;
+        use unsafe_math_trait::FastAdd;
 
         let max: u8 = u8::MAX;
         let one: u8 = 1;
@@ -41,9 +185,31 @@ mod tests {
         assert_eq!(fast, wrapped);
     }
 
+    // which backend is used depends on the toolchain, see build.rs of unsafe_math_trait
+
+    #[test]
+    fn test_backend_detection() {
+        assert_eq!(__private::NIGHTLY, cfg!(unsafe_math_nightly));
+    }
+
+    // on stable, integer ops are `unchecked_*` methods (`checked_*` with `None` assumed away for division),
+    // so they agree with `checked_*` wherever defined
+    #[test]
+    #[cfg(not(unsafe_math_nightly))]
+    fn test_stable_integer_backend() {
+        use unsafe_math_trait::{FastAdd, FastMul, FastShl};
+
+        let max: u8 = u8::MAX;
+        assert_eq!(max.fast_add(0), max);
+        assert_eq!(max.try_fast_add(1), None);
+        assert_eq!(16u8.fast_mul(15), 240);
+        assert_eq!(1u8.fast_shl(7), 128);
+        assert_eq!(1u8.try_fast_shl(8), None);
+    }
+
     #[test]
     fn test_integer_other_ops() {
-        use unsafe_math_trait::UnsafeMath;
+        use unsafe_math_trait::{FastDiv, FastMul, FastRem, FastShl, FastShr, FastSub};
 
         let x: i16 = -5;
         let y: i16 = 3;
@@ -58,7 +224,7 @@ mod tests {
 
     #[test]
     fn test_float_fast_math() {
-        use unsafe_math_trait::UnsafeMath;
+        use unsafe_math_trait::{FastAdd, FastDiv, FastMul, FastRem, FastSub};
 
         let f: f32 = 1.5;
         let g: f32 = 2.25;
@@ -69,17 +235,30 @@ mod tests {
         assert_eq!(f.fast_rem(g), f.rem(g));
     }
 
+    #[unsafe_math]
     fn calc_int(a: u32, b: u32) -> u32 {
-        #[unsafe_math]
-        {
-            a * b + a - b
-        }
+        a * b + a - b
     }
 
     #[test]
     fn test_calc_int_agrees() {
         for i in 0..50_u32 {
-            for j in 0..50_u32 {
+            for j in 0..=i {
+                assert_eq!(
+                    calc_int(i, j),
+                    i.wrapping_mul(j).wrapping_add(i).wrapping_sub(j)
+                );
+            }
+        }
+    }
+
+    // in checked mode (debug builds or --cfg unsafe_math_checked) overflow panics instead of being UB
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "unsafe_math")]
+    fn test_calc_int_overflow() {
+        for i in 0..50_u32 {
+            for j in i..50_u32 {
                 assert_eq!(
                     calc_int(i, j),
                     i.wrapping_mul(j).wrapping_add(i).wrapping_sub(j)
@@ -88,11 +267,13 @@ mod tests {
         }
     }
 
+    #[allow(clippy::unnecessary_cast)]
     fn sum_of_squares_ub_wraps(n: u16) -> u16 {
         let mut sum: u16 = 0;
-        #[unsafe_math]
-        for i in 0..=n {
-            sum += i as u16 * i as u16;
+        unsafe_math_block! {
+            for i in 0..=n {
+                sum += i as u16 * i as u16;
+            }
         }
         sum as u16
     }
@@ -101,6 +282,13 @@ mod tests {
     fn test_sum_of_squares() {
         assert_eq!(sum_of_squares_ub_wraps(0), 0);
         assert_eq!(sum_of_squares_ub_wraps(1), 1);
+        assert_eq!(sum_of_squares_ub_wraps(20), 2870);
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "unsafe_math")]
+    fn test_sum_of_squares_overflow() {
         assert_eq!(
             sum_of_squares_ub_wraps(420),
             (0..=420)
@@ -132,6 +320,7 @@ mod tests {
         };
     }
 
+    #[allow(clippy::approx_constant)]
     #[test]
     fn test_calc_float_agrees() {
         for &x in &[0.0f32, 1.5, -2.3, 3.14] {
@@ -221,7 +410,12 @@ mod tests {
         let d = 3_i32;
         let expected = ((a + b) * c) / d;
         assert_eq!(chained_ops_int(a, b, c, d), expected);
+    }
 
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "unsafe_math")]
+    fn test_chained_ops_int_overflow() {
         let a_ov = i32::MAX - 10;
         let b_ov = 20;
         let c_ov = 2;
@@ -247,11 +441,9 @@ mod tests {
         assert_float_eq!(chained_ops_float(a, b, c, d), expected, epsilon);
     }
 
+    #[unsafe_math]
     fn single_operand_parentheses(a: u32, b: u32) -> u32 {
-        #[unsafe_math]
-        {
-            (a) + (b)
-        }
+        (a) + (b)
     }
 
     #[test]
@@ -278,6 +470,7 @@ mod tests {
         assert_eq!(parentheses_around_function_call(x), expected);
     }
 
+    #[allow(clippy::approx_constant)]
     fn mock_float_function() -> f32 {
         3.14
     }
@@ -318,4 +511,1855 @@ mod tests {
             expected_neg
         );
     }
+
+    // operator selection
+
+    #[unsafe_math(add, mul)]
+    fn selected_add_mul(a: u32, b: u32, c: u32) -> u32 {
+        a * b + a / c
+    }
+
+    #[unsafe_math(not(div, rem))]
+    fn excluded_div_rem(a: i32, b: i32) -> i32 {
+        (a + b) % b
+    }
+
+    #[test]
+    fn test_selected_ops() {
+        assert_eq!(selected_add_mul(6, 7, 2), 45);
+        assert_eq!(excluded_div_rem(10, 3), 1);
+    }
+
+    #[test]
+    #[cfg_attr(any(debug_assertions, unsafe_math_checked), should_panic(expected = "unsafe_math"))]
+    #[cfg_attr(
+        not(any(unsafe_math_nightly, debug_assertions, unsafe_math_checked)),
+        ignore = "overflow is UB, only the nightly intrinsics wrap"
+    )]
+    fn test_selected_ops_overflow() {
+        assert_eq!(
+            selected_add_mul(u32::MAX, 2, 1),
+            u32::MAX.wrapping_mul(2).wrapping_add(u32::MAX)
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_unselected_div_still_panics() {
+        selected_add_mul(1, 1, std::hint::black_box(0));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_excluded_rem_still_panics() {
+        excluded_div_rem(1, std::hint::black_box(0));
+    }
+
+    fn block_selected_shl(a: u32, b: u32) -> u32 {
+        unsafe_math_block! {
+            #![unsafe_math(shl)]
+            (a << 3) / b
+        }
+    }
+
+    #[test]
+    fn test_block_selected_ops() {
+        assert_eq!(block_selected_shl(5, 4), 10);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_block_unselected_div_still_panics() {
+        block_selected_shl(1, std::hint::black_box(0));
+    }
+
+    // opting out
+
+    #[unsafe_math]
+    fn buffer_size(len: usize, size: usize, header: usize) -> usize {
+        safe_math!(len * size) + header
+    }
+
+    #[unsafe_math]
+    fn per_item(total: u32, count: u32) -> u32 {
+        #[safe_math]
+        let each = total / count;
+        each * 2
+    }
+
+    #[unsafe_math]
+    fn scaled_sum(a: u8, b: u8, scale: u8) -> u8 {
+        let mut sum = b;
+        #[safe_math]
+        {
+            #[unsafe_math]
+            let scaled = a * scale;
+            sum += scaled;
+        }
+        sum
+    }
+
+    #[test]
+    fn test_safe_math() {
+        assert_eq!(buffer_size(4, 8, 16), 48);
+        assert_eq!(per_item(10, 5), 4);
+        assert_eq!(scaled_sum(10, 5, 3), 35);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "attempt to multiply with overflow")]
+    fn test_safe_math_expr_overflow_panics() {
+        buffer_size(usize::MAX, 2, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "attempt to divide by zero")]
+    fn test_safe_math_stmt_div_by_zero_panics() {
+        per_item(1, std::hint::black_box(0));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "attempt to add with overflow")]
+    fn test_safe_math_block_overflow_panics() {
+        scaled_sum(100, 200, 1);
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "unsafe_math")]
+    fn test_unsafe_math_inside_safe_math() {
+        scaled_sum(100, 1, 3);
+    }
+
+    // checked mode. Without it these would be actual UB, so they only run when it is on
+
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[unsafe_math]
+    fn checked_div(a: i32, b: i32) -> i32 {
+        a / b
+    }
+
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[unsafe_math]
+    fn checked_shl(a: u32, b: u32) -> u32 {
+        a << b
+    }
+
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[unsafe_math(float(all))]
+    fn checked_float_mul(a: f32, b: f32) -> f32 {
+        a * b
+    }
+
+    #[unsafe_math]
+    fn default_float_mul(a: f32, b: f32) -> f32 {
+        a * b
+    }
+
+    #[test]
+    fn test_default_float_sound() {
+        // no nnan/ninf by default, so non-finite values are not UB
+        assert_eq!(default_float_mul(f32::MAX, std::hint::black_box(2.0)), f32::INFINITY);
+        assert!(default_float_mul(f32::INFINITY, std::hint::black_box(0.0)).is_nan());
+        assert!(float_methods(-1.0, 0.0).0.is_nan());
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "`a / b`")]
+    fn test_checked_div_by_zero() {
+        checked_div(1, std::hint::black_box(0));
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "`a / b`")]
+    fn test_checked_div_overflow() {
+        checked_div(i32::MIN, std::hint::black_box(-1));
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "`a << b`")]
+    fn test_checked_shl_out_of_range() {
+        checked_shl(1, std::hint::black_box(40));
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "`a * b`")]
+    fn test_checked_float_infinity() {
+        checked_float_mul(f32::MAX, std::hint::black_box(2.0));
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    fn test_checked_reports_location() {
+        let panic = std::panic::catch_unwind(|| checked_div(1, std::hint::black_box(0))).unwrap_err();
+        let message = panic.downcast_ref::<String>().unwrap();
+        assert!(message.contains(file!()), "{message}");
+    }
+
+    // compound assignment evaluates its place only once
+
+    struct Counter {
+        calls: usize,
+    }
+
+    impl Counter {
+        fn next_index(&mut self) -> usize {
+            self.calls += 1;
+            self.calls - 1
+        }
+    }
+
+    #[unsafe_math]
+    fn side_effecting_index(arr: &mut [u32; 4], counter: &mut Counter) {
+        arr[counter.next_index()] += 10;
+        arr[counter.next_index()] *= 3;
+    }
+
+    #[test]
+    fn test_compound_assign_index_evaluated_once() {
+        let mut arr = [1, 2, 3, 4];
+        let mut counter = Counter { calls: 0 };
+        side_effecting_index(&mut arr, &mut counter);
+        assert_eq!(counter.calls, 2);
+        assert_eq!(arr, [11, 6, 3, 4]);
+    }
+
+    #[unsafe_math]
+    fn double_all(values: &mut [i64]) {
+        let len = values.len();
+        let mut iter = values.iter_mut();
+        for _ in 0..len {
+            *iter.next().unwrap() *= 2;
+        }
+    }
+
+    #[test]
+    fn test_compound_assign_deref() {
+        let mut values = [1, -2, 3];
+        double_all(&mut values);
+        assert_eq!(values, [2, -4, 6]);
+    }
+
+    struct Particle {
+        pos: Pos,
+    }
+
+    struct Pos {
+        xy: [f32; 2],
+    }
+
+    #[unsafe_math]
+    fn move_particles(particles: &mut [Particle], step: &mut usize) {
+        particles[{ *step += 1; *step - 1 }].pos.xy[1] -= 0.5;
+        // right side is evaluated first and may read the place
+        particles[0].pos.xy[0] += particles[0].pos.xy[1];
+    }
+
+    #[test]
+    fn test_compound_assign_field_chain() {
+        let mut particles = [Particle { pos: Pos { xy: [1.0, 2.0] } }];
+        let mut step = 0;
+        move_particles(&mut particles, &mut step);
+        assert_eq!(step, 1);
+        assert_eq!(particles[0].pos.xy, [2.5, 1.5]);
+    }
+
+    // operands of the same type give that type, so integer literals are inferred from how the result is used
+
+    #[unsafe_math]
+    fn inferred_literals() -> (u16, i8) {
+        let a = 10;
+        let b = -2;
+        (a * 3, b - 1)
+    }
+
+    #[unsafe_math(int)]
+    fn inferred_literals_only() -> u16 {
+        let a = 10;
+        let b = a + 2;
+        b * 3
+    }
+
+    #[test]
+    fn test_inferred_literals() {
+        assert_eq!(inferred_literals(), (30, -3));
+        assert_eq!(inferred_literals_only(), 36);
+    }
+
+    // builtin compound assignment evaluates the right side first, overloaded one evaluates the place first
+
+    struct Total(u32);
+
+    impl AddAssign<u32> for Total {
+        fn add_assign(&mut self, rhs: u32) {
+            self.0 += rhs;
+        }
+    }
+
+    #[unsafe_math]
+    fn logged_assign(totals: &mut [Total], counts: &mut [u32], log: &mut Vec<&'static str>) {
+        totals[{ log.push("total"); 0 }] += { log.push("rhs"); 5 };
+        counts[{ log.push("count"); 0 }] += { log.push("rhs"); 5 };
+    }
+
+    #[test]
+    fn test_compound_assign_order() {
+        let mut totals = [Total(1)];
+        let mut counts = [1];
+        let mut log = Vec::new();
+        logged_assign(&mut totals, &mut counts, &mut log);
+        assert_eq!(log, ["total", "rhs", "rhs", "count"]);
+        assert_eq!((totals[0].0, counts[0]), (6, 6));
+    }
+
+    #[unsafe_math]
+    fn logged_vek_assign(points: &mut [qvek::vek::Vec2<i32>], log: &mut Vec<&'static str>) {
+        points[{ log.push("point"); 0 }] += { log.push("rhs"); qvek::vek::Vec2::one() };
+    }
+
+    #[test]
+    fn test_compound_assign_order_copy() {
+        // `Vec2` has a fast path and is `Copy`, but its compound assignment is still overloaded
+        let mut points = [qvek::vek::Vec2::new(1, 2)];
+        let mut log = Vec::new();
+        logged_vek_assign(&mut points, &mut log);
+        assert_eq!(log, ["point", "rhs"]);
+        assert_eq!(points[0], qvek::vek::Vec2::new(2, 3));
+    }
+
+    // each order borrows like the original does
+    #[unsafe_math]
+    fn assign_borrows(
+        counts: &mut [u32],
+        points: &mut [qvek::vek::Vec3<f32>; 2],
+        cell: &std::cell::RefCell<(u32, qvek::vek::Vec3<f32>)>,
+    ) -> Option<qvek::vek::Vec3<f32>> {
+        counts[0] += counts[1];
+        points[0] += qvek::vek::Vec3::broadcast(points.len() as f32);
+        let mut point = points[1];
+        point += point;
+        cell.borrow_mut().0 += 1;
+        cell.borrow_mut().1 *= 2.0;
+        points[1] -= counts.get(5).map(|&count| qvek::vek::Vec3::broadcast(count as f32))?;
+        Some(point)
+    }
+
+    #[test]
+    fn test_compound_assign_borrows() {
+        let mut counts = [1, 2];
+        let mut points = [qvek::vek::Vec3::one(); 2];
+        let cell = std::cell::RefCell::new((1, qvek::vek::Vec3::one()));
+        assert_eq!(assign_borrows(&mut counts, &mut points, &cell), None);
+        assert_eq!(counts, [3, 2]);
+        assert_eq!(points, [qvek::vek::Vec3::broadcast(3.0), qvek::vek::Vec3::one()]);
+        assert_eq!(cell.into_inner(), (2, qvek::vek::Vec3::broadcast(2.0)));
+    }
+
+    fn take_len(s: String) -> usize {
+        s.len()
+    }
+
+    // operands are evaluated once, moving `label` and nesting compound assignments in them work like without the macro
+    #[unsafe_math]
+    fn assign_once(lens: &mut [usize; 2], sums: &mut [u32; 2], label: String, calls: &mut u32) -> u32 {
+        lens[{ *calls += 1; 1 }] += take_len(label);
+        sums[{ *calls += 1; 0 }] += { sums[{ *calls += 1; 1 }] += { *calls += 1; 2 }; 3 };
+        *calls
+    }
+
+    #[test]
+    fn test_compound_assign_once() {
+        let mut lens = [0, 1];
+        let mut sums = [1, 1];
+        assert_eq!(assign_once(&mut lens, &mut sums, "four".to_string(), &mut 0), 4);
+        assert_eq!(lens, [0, 5]);
+        assert_eq!(sums, [4, 3]);
+    }
+
+    #[unsafe_math]
+    fn shadowing_names(place: &mut u32, rhs: u32) {
+        *place += rhs;
+    }
+
+    #[test]
+    fn test_compound_assign_hygiene() {
+        let mut place = 1;
+        shadowing_names(&mut place, 2);
+        assert_eq!(place, 3);
+    }
+
+    // types without UnsafeMath fall back to regular operators
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Matrix2([[i32; 2]; 2]);
+
+    impl Mul for Matrix2 {
+        type Output = Matrix2;
+        fn mul(self, rhs: Matrix2) -> Matrix2 {
+            let (a, b) = (self.0, rhs.0);
+            Matrix2([
+                [a[0][0] * b[0][0] + a[0][1] * b[1][0], a[0][0] * b[0][1] + a[0][1] * b[1][1]],
+                [a[1][0] * b[0][0] + a[1][1] * b[1][0], a[1][0] * b[0][1] + a[1][1] * b[1][1]],
+            ])
+        }
+    }
+
+    #[unsafe_math]
+    fn mixed_types(
+        name: String,
+        elapsed: std::time::Duration,
+        m: Matrix2,
+        x: u32,
+    ) -> (String, std::time::Duration, Matrix2, u32) {
+        let mut greeting = name + ", hi";
+        greeting += "!";
+        let mut total = elapsed + std::time::Duration::from_millis(x as u64 * 2);
+        total -= std::time::Duration::from_millis(1);
+        (greeting, total, m * m, x * x + 1)
+    }
+
+    #[test]
+    fn test_fallback_to_std_ops() {
+        let m = Matrix2([[1, 2], [3, 4]]);
+        let (greeting, total, squared, x) = mixed_types("bob".to_string(), std::time::Duration::from_millis(5), m, 3);
+        assert_eq!(greeting, "bob, hi!");
+        assert_eq!(total, std::time::Duration::from_millis(10));
+        assert_eq!(squared, Matrix2([[7, 10], [15, 22]]));
+        assert_eq!(x, 10);
+    }
+
+    #[unsafe_math]
+    fn generic_sum<T: Add<Output = T> + Copy>(values: &[T], zero: T) -> T {
+        let mut acc = zero;
+        for &v in values {
+            acc = acc + v;
+        }
+        acc
+    }
+
+    #[test]
+    fn test_fallback_generic() {
+        assert_eq!(generic_sum(&[1u8, 2, 3], 0), 6);
+        assert_eq!(generic_sum(&[1.5f64, 2.5], 0.0), 4.0);
+    }
+
+    // path to the crate in generated code
+
+    mod facade {
+        pub use crate as math;
+    }
+
+    #[unsafe_math(crate = facade::math)]
+    fn through_facade(a: u32, b: u32) -> u32 {
+        a * b + 1
+    }
+
+    // no `UnsafeMath` in scope here, and a local item shadows the name
+    mod shadowed {
+        #[allow(dead_code)]
+        struct UnsafeMath;
+
+        #[crate::unsafe_math]
+        pub fn shadowed_trait_name(a: i64, b: i64) -> i64 {
+            (a - b) * 2
+        }
+    }
+
+    macro_rules! define_kernel {
+        ($name:ident, $t:ty) => {
+            #[$crate::unsafe_math(crate = $crate)]
+            fn $name(a: $t, b: $t) -> $t {
+                a * a + b
+            }
+        };
+    }
+
+    define_kernel!(kernel_u16, u16);
+    define_kernel!(kernel_f64, f64);
+
+    #[test]
+    fn test_crate_path() {
+        assert_eq!(through_facade(6, 7), 43);
+        assert_eq!(shadowed::shadowed_trait_name(5, 8), -6);
+        assert_eq!(kernel_u16(3, 4), 13);
+        assert_eq!(kernel_f64(1.5, 0.75), 3.0);
+
+        let block = unsafe_math_block! {
+            #![unsafe_math(crate = self::facade::math)]
+            2u8 << 3
+        };
+        assert_eq!(block, 16);
+    }
+
+    // arguments of macro invocations
+
+    macro_rules! twice {
+        ($e:expr) => {
+            $e * 2
+        };
+    }
+
+    #[unsafe_math]
+    fn inside_macros(a: i32, b: i32) -> (Vec<i32>, String, i32) {
+        assert_eq!(a + b, b + a, "{} + {}", a, b);
+        let v = vec![a * b; 2];
+        let s = format!("{}", a / b);
+        (v, s, twice!(a - b))
+    }
+
+    #[test]
+    fn test_inside_macros() {
+        assert_eq!(inside_macros(7, 2), (vec![14, 14], "3".to_string(), 10));
+    }
+
+    // checked mode panics only if the arithmetic inside the macro was rewritten
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "unsafe_math")]
+    fn test_inside_macros_rewritten() {
+        inside_macros(i32::MAX, 1);
+    }
+
+    #[cfg(debug_assertions)]
+    #[unsafe_math(macros(not(vec)))]
+    fn denied_macro(a: u8, n: usize) -> Vec<u8> {
+        vec![a + 1; n]
+    }
+
+    #[cfg(debug_assertions)]
+    #[unsafe_math(macros(assert))]
+    fn allowed_macro(a: u8, b: u8) -> Vec<u8> {
+        assert!(a + b >= a);
+        vec![a - b]
+    }
+
+    #[test]
+    #[should_panic(expected = "attempt to add with overflow")]
+    #[cfg(debug_assertions)]
+    fn test_denied_macro_untouched() {
+        denied_macro(u8::MAX, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "attempt to subtract with overflow")]
+    #[cfg(debug_assertions)]
+    fn test_not_allowed_macro_untouched() {
+        allowed_macro(1, 2);
+    }
+
+    // macros that use the text of their arguments or take patterns are left alone
+
+    #[unsafe_math]
+    fn text_macros(a: i32, b: i32) -> (&'static str, i32) {
+        (stringify!(a + b), a + b)
+    }
+
+    #[test]
+    fn test_text_macros_untouched() {
+        assert_eq!(text_macros(1, 2), ("a + b", 3));
+    }
+
+    #[unsafe_math]
+    fn assert_small_sum(a: u8, b: u8) {
+        assert!(a + b < 3);
+        debug_assert!(a * b < 1,);
+    }
+
+    #[test]
+    #[should_panic(expected = "assertion failed: a + b < 3")]
+    fn test_assert_message_untouched() {
+        assert_small_sum(2, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "assertion failed: a * b < 1")]
+    #[cfg(debug_assertions)]
+    fn test_debug_assert_message_untouched() {
+        assert_small_sum(1, 1);
+    }
+
+    #[unsafe_math]
+    fn pattern_macros(a: u8, b: i32) -> (bool, bool, i32) {
+        let sign = match b {
+            i32::MIN..=-1 => -1,
+            0 => 0,
+            _ => 1,
+        };
+        (matches!(a + 1, 0), matches!(b, -1 | 1), sign)
+    }
+
+    #[test]
+    fn test_pattern_macros_untouched() {
+        assert_eq!(pattern_macros(1, -1), (false, true, -1));
+    }
+
+    #[test]
+    #[should_panic(expected = "attempt to add with overflow")]
+    #[cfg(debug_assertions)]
+    fn test_pattern_macros_not_rewritten() {
+        pattern_macros(u8::MAX, 0);
+    }
+
+    macro_rules! not_expressions {
+        ($($name:ident => $value:expr;)*) => {
+            [$(($value, stringify!($name))),*]
+        };
+    }
+
+    #[unsafe_math]
+    fn unparsable_macro_args(a: u32) -> [(u32, &'static str); 2] {
+        not_expressions! {
+            one => a + 1;
+            two => a + 2;
+        }
+    }
+
+    #[test]
+    fn test_unparsable_macro_args() {
+        assert_eq!(unparsable_macro_args(1), [(2, "one"), (3, "two")]);
+    }
+
+    // macro_rules generated code. Fragments come wrapped in invisible groups, which must behave like parentheses
+
+    macro_rules! kernel_fn {
+        (#[$attr:meta] $fname:ident($a:ident, $b:ident): $t:ty => $x:expr, $y:expr, $z:expr) => {
+            #[$attr]
+            pub fn $fname($a: $t, $b: $t) -> ($t, $t) {
+                let mut acc = $x * $y - $z;
+                acc += $x % $y;
+                acc /= $z;
+                ($x * $y + $z / $y, acc)
+            }
+        };
+    }
+
+    // plain, rewritten and not rewritten (precedence kept by parentheses alone) versions of the same kernel
+    macro_rules! kernels {
+        ($($name:ident($a:ident, $b:ident): $t:ty => $x:expr, $y:expr, $z:expr;)*) => {
+            $(
+                mod $name {
+                    kernel_fn!(#[inline] plain($a, $b): $t => $x, $y, $z);
+                    kernel_fn!(#[crate::unsafe_math] fast($a, $b): $t => $x, $y, $z);
+                    kernel_fn!(
+                        #[crate::unsafe_math(not(add, sub, mul, div, rem, shl, shr))]
+                        untouched($a, $b): $t => $x, $y, $z
+                    );
+                }
+            )*
+        };
+    }
+
+    // fragments passed through one more macro are wrapped twice
+    macro_rules! wrapped_kernel {
+        ($name:ident($a:ident, $b:ident) => $e:expr) => {
+            kernels! {
+                $name($a, $b): u32 => $e, $e + 1, $e - $b;
+            }
+        };
+    }
+
+    kernels! {
+        kernel_sum(a, b): u32 => a + b, b + 1, a - b;
+        kernel_signed(a, b): i32 => a - b, b * 2 + 1, -a;
+        kernel_shift(a, b): u32 => a << 2, b >> 1 | 1, a ^ b;
+        kernel_float(a, b): f64 => a * 0.5, b + 1.0, a - b;
+    }
+
+    wrapped_kernel!(kernel_wrapped(a, b) => a * 2 + b);
+
+    #[test]
+    fn test_macro_generated_kernels() {
+        for a in 10..30_u32 {
+            for b in 0..8_u32 {
+                let expected = kernel_sum::plain(a, b);
+                assert_eq!(kernel_sum::fast(a, b), expected);
+                assert_eq!(kernel_sum::untouched(a, b), expected);
+
+                let expected = kernel_shift::plain(a, b);
+                assert_eq!(kernel_shift::fast(a, b), expected);
+                assert_eq!(kernel_shift::untouched(a, b), expected);
+
+                let expected = kernel_wrapped::plain(a, b);
+                assert_eq!(kernel_wrapped::fast(a, b), expected);
+                assert_eq!(kernel_wrapped::untouched(a, b), expected);
+
+                let (sa, sb) = (a as i32 - 20, b as i32 - 4);
+                if sa != 0 {
+                    let expected = kernel_signed::plain(sa, sb);
+                    assert_eq!(kernel_signed::fast(sa, sb), expected);
+                    assert_eq!(kernel_signed::untouched(sa, sb), expected);
+                }
+
+                let (fa, fb) = (a as f64 * 0.5, b as f64 * 0.25 + 0.5);
+                let expected = kernel_float::plain(fa, fb);
+                assert_float_eq!(kernel_float::fast(fa, fb).0, expected.0, 1e-9);
+                assert_float_eq!(kernel_float::fast(fa, fb).1, expected.1, 1e-9);
+                assert_eq!(kernel_float::untouched(fa, fb), expected);
+            }
+        }
+    }
+
+    // unary minus
+
+    #[unsafe_math]
+    fn negate(a: i32, b: f32, v: [i64; 2]) -> (i32, f32, [i64; 2], i32) {
+        (-a, -b * 2.0, [-v[0], -(v[1] - 1)], -2147483648)
+    }
+
+    // no `nsz`, so the sign of zero is kept
+    #[unsafe_math(float())]
+    fn negate_exact(b: f32) -> f32 {
+        -b * 2.0
+    }
+
+    #[unsafe_math(not(neg))]
+    fn negate_untouched(a: i32) -> i32 {
+        -a
+    }
+
+    // falls back to std::ops::Neg
+    #[unsafe_math]
+    fn negate_matrix(m: Matrix2) -> Matrix2 {
+        -m
+    }
+
+    impl std::ops::Neg for Matrix2 {
+        type Output = Matrix2;
+        fn neg(self) -> Matrix2 {
+            Matrix2(self.0.map(|row| row.map(|x| -x)))
+        }
+    }
+
+    #[test]
+    fn test_neg() {
+        assert_eq!(negate(5, 1.5, [3, 4]), (-5, -3.0, [-3, -3], i32::MIN));
+        assert_eq!(negate(i32::MAX, -0.0, [i64::MIN + 1, 1]), (-i32::MAX, 0.0, [i64::MAX, 0], i32::MIN));
+        assert!(negate_exact(0.0).is_sign_negative());
+        assert_eq!(negate_untouched(7), -7);
+        assert_eq!(negate_matrix(Matrix2([[1, -2], [3, 0]])), Matrix2([[-1, 2], [-3, 0]]));
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "`-a`")]
+    fn test_neg_overflow_checked() {
+        negate(i32::MIN, 0.0, [0, 0]);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "attempt to negate with overflow")]
+    fn test_neg_excluded() {
+        negate_untouched(i32::MIN);
+    }
+
+    // numeric methods
+
+    #[unsafe_math]
+    fn int_methods(a: i32, b: u64, c: i64) -> (i32, u64, i64, i64, i32) {
+        (a.abs() + 1, b.pow(3), c.div_euclid(4), c.rem_euclid(4), (a - 10).abs().pow(2))
+    }
+
+    #[unsafe_math]
+    fn float_methods(x: f32, y: f64) -> (f32, f64, f32, f64, f32) {
+        (x.sqrt(), y.powi(3), x.mul_add(2.0, 1.0), (-y).abs(), x.rem_euclid(-3.0))
+    }
+
+    #[unsafe_math(not(methods))]
+    fn methods_untouched(a: i32) -> i32 {
+        a.abs()
+    }
+
+    #[test]
+    fn test_numeric_methods() {
+        assert_eq!(int_methods(-5, 4, -7), (6, 64, -2, 1, 225));
+        let (sqrt, powi, mul_add, abs, rem) = float_methods(4.0, 1.5);
+        assert_eq!((sqrt, powi, abs, rem), (2.0, 3.375, 1.5, 1.0));
+        assert_float_eq!(mul_add, 9.0, 1e-6);
+        assert_eq!(methods_untouched(-3), 3);
+    }
+
+    // not Copy, and methods have the same names as numeric ones
+    #[derive(Debug, PartialEq)]
+    struct Poly(Vec<i64>);
+
+    impl Poly {
+        fn pow(&self, exp: u8) -> i64 {
+            self.0.iter().map(|c| c.pow(exp as u32)).sum()
+        }
+
+        fn abs(self) -> Poly {
+            Poly(self.0.into_iter().map(i64::abs).collect())
+        }
+
+        fn sqrt(&mut self) -> usize {
+            self.0.push(0);
+            self.0.len()
+        }
+    }
+
+    struct Holder {
+        poly: Poly,
+        polys: Vec<Poly>,
+    }
+
+    #[unsafe_math]
+    fn user_methods(mut holder: Holder, values: &[f32]) -> (i64, i64, usize, Poly, f32) {
+        let pow = holder.poly.pow(2) + holder.polys[0].pow(3);
+        let len = holder.poly.sqrt();
+        let abs = holder.poly.abs();
+        let sum = values.iter().map(|x| x.abs()).sum::<f32>();
+        (pow, holder.polys[0].pow(1), len, abs, sum)
+    }
+
+    #[test]
+    fn test_user_methods() {
+        let holder = Holder {
+            poly: Poly(vec![1, -2]),
+            polys: vec![Poly(vec![-1, 3])],
+        };
+        assert_eq!(user_methods(holder, &[-1.0, 2.5]), (31, 2, 3, Poly(vec![1, 2, 0]), 3.5));
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "`a.abs()`")]
+    fn test_abs_overflow_checked() {
+        int_methods(i32::MIN, 0, 0);
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "`b.pow(3)`")]
+    fn test_pow_overflow_checked() {
+        int_methods(0, u64::MAX, 0);
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "`x.sqrt()`")]
+    fn test_sqrt_negative_checked() {
+        nnan_sqrt(-1.0);
+    }
+
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[unsafe_math(float(nnan))]
+    fn nnan_sqrt(x: f32) -> f32 {
+        x.sqrt()
+    }
+
+    // unchecked indexing
+
+    #[unsafe_math(index)]
+    fn sliding_sum(data: &[u32], window: usize) -> Vec<u32> {
+        let mut out = vec![0; data.len() - window + 1];
+        for i in 0..out.len() {
+            for j in 0..window {
+                out[i] += data[i + j];
+            }
+        }
+        out
+    }
+
+    #[unsafe_math(index)]
+    fn index_places(grid: &mut Vec<Vec<i32>>, row: &mut [i32; 4], text: &str) -> (i32, String, usize) {
+        grid[1][2] = row[0] * 2;
+        grid[0].push(row[3]);
+        let tail = &mut row[1..];
+        tail[0] = -1;
+        for x in &mut grid[1][..2] {
+            *x += 1;
+        }
+        let mut lens = std::collections::HashMap::new();
+        lens.insert("text", text.len());
+        (grid[0][grid[0].len() - 1] + row[1], text[1..3].to_string(), lens["text"])
+    }
+
+    #[test]
+    fn test_index() {
+        assert_eq!(sliding_sum(&[1, 2, 3, 4, 5], 2), [3, 5, 7, 9]);
+        let mut grid = vec![vec![1, 2, 3], vec![4, 5, 6]];
+        let mut row = [10, 20, 30, 40];
+        assert_eq!(index_places(&mut grid, &mut row, "hello"), (39, "el".to_string(), 5));
+        assert_eq!(grid, [vec![1, 2, 3, 40], vec![5, 6, 20]]);
+        assert_eq!(row, [10, -1, 30, 40]);
+    }
+
+    fn make() -> [u8; 3] {
+        [1, 2, 3]
+    }
+
+    // bases that are temporaries live as long as they do without the macro
+    #[unsafe_math(index)]
+    fn index_temporaries(s: &str, mut v: Vec<u8>, i: usize) -> (u8, u8, Vec<u8>) {
+        let byte = s.as_bytes()[i];
+        v.as_mut_slice()[i] = make()[1];
+        (byte, { make()[2] }, v)
+    }
+
+    #[test]
+    fn test_index_temporaries() {
+        assert_eq!(index_temporaries("abc", vec![0; 3], 1), (b'b', 3, vec![0, 2, 0]));
+    }
+
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[unsafe_math(index)]
+    fn pick(data: &[u32], i: usize) -> u32 {
+        data[i]
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "`data[i]` at")]
+    fn test_index_checked() {
+        pick(&[1, 2, 3], 3);
+    }
+
+    // unchecked casts
+
+    #[derive(Clone, Copy)]
+    enum Channel {
+        Green = 1,
+    }
+
+    #[unsafe_math(cast)]
+    fn casts(x: f32, wide: u64, c: char) -> (u8, i32, u16, u32, i64, usize) {
+        let texel = [0u8; 2];
+        let ptr = &texel as *const [u8; 2] as *const u8;
+        (
+            (x * 255.0) as u8,
+            -x as i32,
+            (wide >> 40) as u16,
+            c as u32 + Channel::Green as u32,
+            ptr as usize as i64 - ptr as usize as i64,
+            2.5 as usize,
+        )
+    }
+
+    #[unsafe_math(cast)]
+    fn sign_changing_casts(x: i32, y: u64) -> (u8, i32, u16) {
+        (x as u8, y as i32, x as u16)
+    }
+
+    #[unsafe_math]
+    fn saturating_cast(x: f32) -> u8 {
+        x as u8
+    }
+
+    #[test]
+    fn test_casts() {
+        assert_eq!(casts(0.5, 0xABCD << 40, 'a'), (127, 0, 0xABCD, 98, 0, 2));
+        // wrap like plain `as`, in checked mode too
+        assert_eq!(sign_changing_casts(-1, u64::MAX), (255, -1, 65535));
+        assert_eq!(saturating_cast(300.0), 255);
+        assert_eq!(saturating_cast(f32::NAN), 0);
+    }
+
+    #[unsafe_math(cast)]
+    fn vek_casts(v: qvek::vek::Vec3<f32>) -> (qvek::vek::Vec3<i16>, qvek::vek::Vec3<f32>) {
+        (v.as_::<i16>(), -v)
+    }
+
+    #[test]
+    fn test_vek_casts() {
+        use qvek::vek::Vec3;
+        assert_eq!(vek_casts(Vec3::new(1.5, -2.5, 300.0)), (Vec3::new(1, -2, 300), Vec3::new(-1.5, 2.5, -300.0)));
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "`(x * 255.0) as u8`")]
+    fn test_float_cast_checked() {
+        casts(2.0, 0, 'a');
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "`(wide >> 40) as u16`")]
+    fn test_narrowing_cast_checked() {
+        casts(0.0, u64::MAX, 'a');
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "`v.as_::<i16>()`")]
+    fn test_vek_cast_checked() {
+        vek_casts(qvek::vek::Vec3::new(0.0, 1e6, 0.0));
+    }
+
+    // shift amount of any integer type, like std::ops::Shl
+
+    #[unsafe_math]
+    fn shifts(x: u64, bits: usize, small: u8, signed: i64) -> (u64, u64, i32) {
+        let mut packed = x << bits;
+        packed >>= small;
+        (packed, x >> signed, -8 >> small)
+    }
+
+    #[test]
+    fn test_shift_amount_types() {
+        assert_eq!(shifts(0xFF, 8, 4, 4), (0xFF0, 0xF, -1));
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "`x << bits`")]
+    fn test_shift_amount_too_large() {
+        shifts(1, 64, 0, 0);
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "`x >> signed`")]
+    fn test_shift_amount_negative() {
+        shifts(1, 0, 0, -1);
+    }
+
+    // vek types with scalars, `v * 0.5` and `2.0 * v`
+
+    use qvek::vek::{Extent2, Rgb, Vec2, Vec3};
+
+    #[unsafe_math]
+    fn lerp(a: Vec3<f32>, b: Vec3<f32>, t: f32) -> Vec3<f32> {
+        a * (1.0 - t) + b * t
+    }
+
+    #[unsafe_math]
+    fn scale(v: Vec2<i32>, k: i32) -> (Vec2<i32>, Vec2<i32>, Vec2<i32>) {
+        let mut scaled = v;
+        scaled *= k;
+        (scaled, 2 * v, v - k)
+    }
+
+    #[unsafe_math]
+    fn dim(color: Rgb<f32>, size: Extent2<u16>) -> (Rgb<f32>, Extent2<u16>) {
+        (0.5 * color, size / 2 + 1)
+    }
+
+    #[test]
+    fn test_vek_scalar_ops() {
+        assert_eq!(
+            lerp(Vec3::new(0.0, 2.0, 4.0), Vec3::new(4.0, 2.0, 0.0), 0.25),
+            Vec3::new(1.0, 2.0, 3.0)
+        );
+        assert_eq!(
+            scale(Vec2::new(1, -2), 3),
+            (Vec2::new(3, -6), Vec2::new(2, -4), Vec2::new(-2, -5))
+        );
+        assert_eq!(
+            dim(Rgb::new(1.0, 0.5, 0.0), Extent2::new(640, 480)),
+            (Rgb::new(0.5, 0.25, 0.0), Extent2::new(321, 241))
+        );
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "`scaled *= k`")]
+    fn test_vek_scalar_checked() {
+        scale(Vec2::new(1, i32::MAX), 2);
+    }
+
+    // reference operands, as they come out of iterators
+
+    #[unsafe_math]
+    fn sum_of_squares(data: &[f32]) -> f32 {
+        data.iter().map(|x| x * x).sum()
+    }
+
+    #[unsafe_math]
+    fn ref_ops(data: &[u64], points: &[Vec2<i32>], shift: &u8) -> (u64, u64, Vec2<i32>) {
+        let mut sum = 0;
+        for x in data {
+            sum += x;
+        }
+        let first = &data[0];
+        let mut offset = Vec2::<i32>::zero();
+        for p in points {
+            offset += p * 2;
+        }
+        (sum + &data[1], (first - &data[1]) << shift, offset - &points[0])
+    }
+
+    #[test]
+    fn test_ref_operands() {
+        assert_eq!(sum_of_squares(&[1.0, 2.0, 3.0]), 14.0);
+        let points = [Vec2::new(1, 2), Vec2::new(3, 4)];
+        assert_eq!(ref_ops(&[7, 3], &points, &2), (13, 16, Vec2::new(7, 10)));
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "`first - &data[1]`")]
+    fn test_ref_operands_checked() {
+        ref_ops(&[3, 7], &[Vec2::zero()], &0);
+    }
+
+    #[unsafe_math]
+    fn ref_shifts(x: &u32, data: &[i8], shift: &u32) -> (u32, Vec<i8>) {
+        (x << shift, data.iter().map(|value| value >> 1).collect())
+    }
+
+    #[test]
+    fn test_ref_shifts() {
+        assert_eq!(ref_shifts(&3, &[8, -8], &2), (12, vec![4, -4]));
+    }
+
+    // the fast path reports the expression, plain `<<` would panic with its own message
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "`x << shift`")]
+    fn test_ref_shifts_checked() {
+        ref_shifts(&1, &[], &32);
+    }
+
+    // derived UnsafeMath
+
+    #[derive(Clone, Copy, Debug, PartialEq, UnsafeMath)]
+    struct Body {
+        pos: Vec3<f32>,
+        mass: f32,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, UnsafeMath)]
+    struct Pixel(u8, u8, u8);
+
+    #[derive(Clone, Copy, Debug, PartialEq, UnsafeMath)]
+    struct Pair<T> {
+        items: Vec2<T>,
+        count: T,
+    }
+
+    // derive only provides fast versions, operators themselves are still needed
+
+    impl std::ops::Add for Body {
+        type Output = Self;
+
+        fn add(self, rhs: Self) -> Self {
+            Body { pos: self.pos + rhs.pos, mass: self.mass + rhs.mass }
+        }
+    }
+
+    impl std::ops::Shl<u8> for Pixel {
+        type Output = Self;
+
+        fn shl(self, rhs: u8) -> Self {
+            Pixel(self.0 << rhs, self.1 << rhs, self.2 << rhs)
+        }
+    }
+
+    impl<T: std::ops::Mul<Output = T>> std::ops::Mul for Pair<T> {
+        type Output = Self;
+
+        fn mul(self, rhs: Self) -> Self {
+            Pair { items: self.items * rhs.items, count: self.count * rhs.count }
+        }
+    }
+
+    impl<T: std::ops::Sub<Output = T>> std::ops::Sub for Pair<T> {
+        type Output = Self;
+
+        fn sub(self, rhs: Self) -> Self {
+            Pair { items: self.items - rhs.items, count: self.count - rhs.count }
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, UnsafeMath)]
+    #[unsafe_math(via_ops, ops(add, sub, mul, div, rem, shl, shr))]
+    struct Wrapping8(u8);
+
+    macro_rules! forward_ops {
+        ($($trait_:ident::$method:ident),*) => {
+            $(
+                impl std::ops::$trait_ for Wrapping8 {
+                    type Output = Self;
+
+                    fn $method(self, rhs: Self) -> Self {
+                        Wrapping8(std::num::Wrapping(self.0).$method(std::num::Wrapping(rhs.0)).0)
+                    }
+                }
+            )*
+        };
+    }
+    forward_ops!(Add::add, Sub::sub, Mul::mul, Div::div, Rem::rem);
+
+    impl std::ops::Shl<u32> for Wrapping8 {
+        type Output = Self;
+
+        fn shl(self, rhs: u32) -> Self {
+            Wrapping8(self.0.wrapping_shl(rhs))
+        }
+    }
+
+    impl std::ops::Shr<u32> for Wrapping8 {
+        type Output = Self;
+
+        fn shr(self, rhs: u32) -> Self {
+            Wrapping8(self.0.wrapping_shr(rhs))
+        }
+    }
+
+    #[unsafe_math]
+    fn derived(body: Body, pixel: Pixel, pair: Pair<i64>, byte: Wrapping8) -> (Body, Pixel, Pair<i64>, Wrapping8) {
+        (body + body, pixel << 1u8, pair * pair - pair, byte * byte + byte)
+    }
+
+    #[test]
+    fn test_derive() {
+        let body = Body { pos: Vec3::new(1.0, 2.0, 3.0), mass: 0.5 };
+        let pair = Pair { items: Vec2::new(2, -3), count: 4 };
+        assert_eq!(
+            derived(body, Pixel(1, 2, 64), pair, Wrapping8(200)),
+            (
+                Body { pos: Vec3::new(2.0, 4.0, 6.0), mass: 1.0 },
+                Pixel(2, 4, 128),
+                Pair { items: Vec2::new(2, 12), count: 12 },
+                Wrapping8(200u8.wrapping_mul(200).wrapping_add(200)),
+            )
+        );
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "`pair * pair`")]
+    fn test_derive_checked() {
+        derived(
+            Body { pos: Vec3::zero(), mass: 0.0 },
+            Pixel(0, 0, 0),
+            Pair { items: Vec2::new(0, i64::MAX), count: 0 },
+            Wrapping8(0),
+        );
+    }
+
+    // per-operator traits: types implement only what they have
+
+    #[derive(Clone, Copy, Debug, PartialEq, UnsafeMath)]
+    #[unsafe_math(via_ops, ops(mul))]
+    struct Rotation(i32);
+
+    impl std::ops::Mul for Rotation {
+        type Output = Self;
+
+        fn mul(self, rhs: Self) -> Self {
+            Rotation(self.0 * rhs.0)
+        }
+    }
+
+    fn fast_total<T: UnsafeMath + Copy>(items: &[T], zero: T) -> T {
+        items.iter().fold(zero, |sum, &item| sum.fast_add(item))
+    }
+
+    #[unsafe_math]
+    fn compose(a: Rotation, b: Rotation, items: &[f32]) -> (Rotation, f32) {
+        (a * b, fast_total(items, 0.0) * 2.0)
+    }
+
+    #[test]
+    fn test_per_operator_traits() {
+        fn has_mul<T: FastMul>() {}
+        has_mul::<Rotation>();
+        assert_eq!(compose(Rotation(-1), Rotation(-1), &[1.0, 2.5]), (Rotation(1), 7.0));
+    }
+
+    // `via_ops` fields can be of any type, the struct has no scalars and keeps plain operators under a types filter
+
+    #[derive(Clone, Copy, Debug, PartialEq, UnsafeMath)]
+    #[unsafe_math(via_ops, ops(add))]
+    struct Elapsed(std::time::Duration);
+
+    impl Add for Elapsed {
+        type Output = Self;
+
+        fn add(self, rhs: Self) -> Self {
+            Elapsed(self.0 + rhs.0)
+        }
+    }
+
+    #[unsafe_math]
+    fn total_time(a: Elapsed, b: Elapsed) -> Elapsed {
+        a + b
+    }
+
+    #[unsafe_math(int)]
+    fn total_time_int(a: Elapsed, b: Elapsed) -> Elapsed {
+        a + b
+    }
+
+    #[test]
+    fn test_via_ops_any_fields() {
+        let (a, b) = (Elapsed(std::time::Duration::from_secs(1)), Elapsed(std::time::Duration::from_millis(500)));
+        assert_eq!(total_time(a, b), Elapsed(std::time::Duration::from_millis(1500)));
+        assert_eq!(total_time_int(a, b), total_time(a, b));
+    }
+
+    // compound assignment of types that can't be copied goes through their own `AddAssign`
+
+    #[derive(Clone, Debug, PartialEq, UnsafeMath)]
+    struct Momentum {
+        x: f32,
+        y: f32,
+    }
+
+    impl std::ops::AddAssign for Momentum {
+        fn add_assign(&mut self, rhs: Self) {
+            self.x += rhs.x;
+            self.y += rhs.y;
+        }
+    }
+
+    #[unsafe_math]
+    fn accumulate(total: &mut Momentum, parts: &[Momentum]) {
+        for part in parts {
+            *total += part.clone();
+        }
+    }
+
+    #[unsafe_math(float)]
+    fn accumulate_only(mut total: Momentum, part: Momentum) -> Momentum {
+        total += part;
+        total
+    }
+
+    #[test]
+    fn test_derive_not_copy() {
+        let mut total = Momentum { x: 1.0, y: 0.0 };
+        accumulate(&mut total, &[Momentum { x: 0.5, y: 2.0 }, Momentum { x: 0.5, y: -1.0 }]);
+        assert_eq!(total, Momentum { x: 2.0, y: 1.0 });
+        assert_eq!(accumulate_only(total, Momentum { x: 1.0, y: 1.0 }), Momentum { x: 3.0, y: 2.0 });
+    }
+
+    // policies
+
+    #[unsafe_math(policy = wrapping)]
+    fn fnv1a(bytes: &[u8]) -> u32 {
+        let mut hash = 0x811c9dc5u32;
+        for &byte in bytes {
+            hash ^= byte as u32;
+            hash *= 0x01000193;
+        }
+        hash + (hash << 40) - -(hash as i32) as u32
+    }
+
+    fn fnv1a_by_hand(bytes: &[u8]) -> u32 {
+        let mut hash = 0x811c9dc5u32;
+        for &byte in bytes {
+            hash ^= byte as u32;
+            hash = hash.wrapping_mul(0x01000193);
+        }
+        hash.wrapping_add(hash.wrapping_shl(40)).wrapping_sub((hash as i32).wrapping_neg() as u32)
+    }
+
+    #[test]
+    fn test_wrapping_policy() {
+        for input in [&b""[..], b"a", b"unsafe_math", &[0xff; 64]] {
+            assert_eq!(fnv1a(input), fnv1a_by_hand(input));
+        }
+    }
+
+    #[unsafe_math(policy = saturating)]
+    fn mix(samples: &[i16], gain: i16, shift: u32, offset: Vec2<u8>, extra: Vec2<u8>) -> (Vec<i16>, Vec2<u8>, f32) {
+        let mixed = samples.iter().map(|&sample| -(sample * gain) << shift).collect();
+        // floats keep their operators
+        (mixed, offset + extra, f32::MAX * 2.0)
+    }
+
+    #[test]
+    fn test_saturating_policy() {
+        let (mixed, offset, float) = mix(&[1000, -1000, i16::MIN, 3], 10, 2, Vec2::new(200, 10), Vec2::new(100, 10));
+        assert_eq!(mixed, [i16::MIN, i16::MAX, i16::MAX, -120]);
+        assert_eq!(offset, Vec2::new(255, 20));
+        assert_eq!(float, f32::INFINITY);
+    }
+
+    #[unsafe_math(policy = checked)]
+    fn checked_sum(a: i32, b: i32) -> i32 {
+        a + b
+    }
+
+    #[test]
+    #[should_panic(expected = "`a + b`")]
+    fn test_checked_policy() {
+        // panics in release builds too
+        checked_sum(i32::MAX, 1);
+    }
+
+    #[unsafe_math(policy = trap, index)]
+    fn trapped(values: &[u64], i: usize) -> u64 {
+        values[i] * values[i + 1]
+    }
+
+    #[test]
+    fn test_trap_policy() {
+        // failing would abort the whole test binary, so only the happy path
+        assert_eq!(trapped(&[3, 5, 7], 1), 35);
+    }
+
+    // float fast-math flags
+
+    const FLAGS: [FloatFlags; 7] = [
+        FloatFlags::REASSOC,
+        FloatFlags::CONTRACT,
+        FloatFlags::NNAN,
+        FloatFlags::NINF,
+        FloatFlags::NSZ,
+        FloatFlags::ARCP,
+        FloatFlags::AFN,
+    ];
+
+    fn flag_combinations() -> impl Iterator<Item = FloatFlags> {
+        (0..1 << FLAGS.len()).map(|bits| {
+            FLAGS
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| bits & 1 << i != 0)
+                .fold(FloatFlags::NONE, |flags, (_, &flag)| flags.union(flag))
+        })
+    }
+
+    #[test]
+    fn test_float_flags_preserve_non_finite() {
+        let (inf, nan) = (f64::INFINITY, f64::NAN);
+        for flags in flag_combinations() {
+            let keeps_inf = !flags.contains(FloatFlags::NINF);
+            let keeps_nan = !flags.contains(FloatFlags::NNAN);
+            if keeps_inf {
+                assert_eq!(inf.fast_add_with(1.0, flags), inf);
+                assert_eq!(1.0.fast_sub_with(inf, flags), -inf);
+                assert_eq!(1e300.fast_mul_with(1e300, flags), inf);
+                assert_eq!(1.0.fast_div_with(0.0, flags), inf);
+                assert_eq!(inf.fast_neg_with(flags), -inf);
+                assert_eq!(inf.fast_sqrt_with(flags), inf);
+                assert_eq!(inf.fast_mul_add_with(2.0, 1.0, flags), inf);
+                assert_eq!(Vec2::new(inf, 1.0).fast_add_with(Vec2::one(), flags), Vec2::new(inf, 2.0));
+            }
+            if keeps_nan {
+                assert!(nan.fast_add_with(1.0, flags).is_nan());
+                assert!(2.0f64.fast_rem_with(0.0, flags).is_nan());
+                assert!((-1.0f64).fast_sqrt_with(flags).is_nan());
+                assert!(nan.fast_abs_with(flags).is_nan());
+            }
+            if keeps_inf && keeps_nan {
+                assert!(inf.fast_sub_with(inf, flags).is_nan());
+                assert!(0.0.fast_mul_with(inf, flags).is_nan());
+            }
+            // checked mode agrees: non-finite values are UB only under the matching flag
+            assert_eq!(inf.try_fast_add_with(1.0, flags).is_some(), keeps_inf);
+            assert_eq!(nan.try_fast_mul_with(1.0, flags).is_some(), keeps_nan);
+            assert_eq!((-1.0f32).try_fast_sqrt_with(flags).is_some(), keeps_nan);
+            assert_eq!(1.0f32.try_fast_rem_with(2.0, flags), Some(1.0));
+        }
+    }
+
+    #[unsafe_math(float(algebraic))]
+    fn reassoc_sum(values: &[f32]) -> (f32, f32) {
+        let mut sum = 0.0;
+        let mut dot = 0.0;
+        for &value in values {
+            sum += value;
+            dot = value.mul_add(value, dot);
+        }
+        (sum, -dot / 2.0)
+    }
+
+    #[unsafe_math(float(reassoc, contract, nsz, arcp, afn))]
+    fn nan_prone(a: f64, b: f64) -> (f64, f64) {
+        (a - b, (a * 0.0).sqrt())
+    }
+
+    #[unsafe_math(float())]
+    fn no_flags(a: f32, b: f32, v: Vec3<f32>) -> (f32, Vec3<f32>) {
+        (a / b, -v * b)
+    }
+
+    #[test]
+    fn test_float_flags() {
+        assert_eq!(reassoc_sum(&[1.0, 2.0, 3.0]), (6.0, -7.0));
+        assert_eq!(reassoc_sum(&[1.0, f32::INFINITY]), (f32::INFINITY, f32::NEG_INFINITY));
+        assert!(reassoc_sum(&[f32::NAN]).0.is_nan());
+
+        let (diff, root) = nan_prone(f64::INFINITY, f64::INFINITY);
+        assert!(diff.is_nan() && root.is_nan());
+
+        let (quotient, v) = no_flags(1.0, 0.0, Vec3::new(1.0, -1.0, f32::NAN));
+        assert_eq!(quotient, f32::INFINITY);
+        assert_eq!((v.x, v.y), (-0.0, 0.0));
+        assert!(v.z.is_nan());
+    }
+
+    // `reassoc` or `contract` get the algebraic intrinsics, other value-changing flags alone have none and stay exact
+
+    #[test]
+    fn test_float_flag_mapping() {
+        for flags in [
+            FloatFlags::REASSOC,
+            FloatFlags::CONTRACT,
+            FloatFlags::REASSOC.union(FloatFlags::NNAN),
+            FloatFlags::CONTRACT.union(FloatFlags::NSZ),
+            FloatFlags::ALGEBRAIC,
+        ] {
+            assert!(flags.algebraic());
+        }
+        for flags in [
+            FloatFlags::NONE,
+            FloatFlags::NSZ.union(FloatFlags::ARCP).union(FloatFlags::AFN),
+            FloatFlags::NNAN.union(FloatFlags::NINF),
+        ] {
+            assert!(!flags.algebraic());
+        }
+    }
+
+    #[unsafe_math(float(reassoc, contract))]
+    fn partial_contract(a: f64, b: f64, c: f64) -> (f64, f64) {
+        (a * b + c, a.mul_add(b, c))
+    }
+
+    #[unsafe_math(float(nsz, arcp))]
+    fn partial_nsz_arcp(a: f64, b: f64, v: Vec3<f32>) -> (f64, f64, Vec3<f32>) {
+        (a - b, a / b, v - Vec3::zero())
+    }
+
+    #[unsafe_math(float(afn, nnan))]
+    fn partial_nnan(a: f32, b: f32) -> f32 {
+        a * b
+    }
+
+    #[test]
+    fn test_float_flag_subsets() {
+        // contraction is allowed, not required: either 0.0 or fused 5.55e-17
+        let (sum, mul_add) = partial_contract(0.1, 10.0, -1.0);
+        for result in [sum, mul_add] {
+            assert!(result == 0.0 || result == 0.1f64.mul_add(10.0, -1.0));
+        }
+
+        let (diff, quotient, v) = partial_nsz_arcp(-0.0, 0.0, Vec3::new(-0.0, 1.0, f32::NAN));
+        assert!(diff == 0.0 && diff.is_sign_negative());
+        assert!(quotient.is_nan());
+        assert!(v.x.is_sign_negative() && v.z.is_nan());
+        // 10.0 * (1.0 / 3.0) is 3.333333333333333
+        assert_eq!(partial_nsz_arcp(10.0, 3.0, Vec3::zero()).1, 3.3333333333333335);
+
+        assert_eq!(partial_nnan(f32::INFINITY, 2.0), f32::INFINITY);
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "`a * b`")]
+    fn test_float_flag_subsets_checked() {
+        partial_nnan(f32::NAN, 2.0);
+    }
+
+    #[unsafe_math(float(algebraic))]
+    fn derived_flags(a: Body, b: Body) -> Body {
+        a + b
+    }
+
+    #[test]
+    fn test_derive_float_flags() {
+        let a = Body { pos: Vec3::new(f32::INFINITY, 1.0, 0.0), mass: f32::NAN };
+        let sum = derived_flags(a, a);
+        assert_eq!(sum.pos, Vec3::new(f32::INFINITY, 2.0, 0.0));
+        assert!(sum.mass.is_nan());
+    }
+
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[unsafe_math(float(algebraic, ninf))]
+    fn assumes_finite(a: f32, b: f32) -> f32 {
+        a * b
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "`a * b`")]
+    fn test_float_flags_checked() {
+        // NaN is fine without nnan, infinity is not with ninf
+        assert!(assumes_finite(f32::NAN, 1.0).is_nan());
+        assumes_finite(f32::MAX, 2.0);
+    }
+
+    // int, float and types(...): other types keep plain operators. nnan makes fast float ops observable,
+    // a NaN goes through plain ones but is caught in checked mode
+
+    #[unsafe_math(int, float(nnan))]
+    fn int_only(a: u8, b: u8, x: f32) -> (u8, f32) {
+        (a + b, x * 2.0)
+    }
+
+    #[unsafe_math(float, float(nnan))]
+    fn float_only(a: u8, b: u8, x: f32) -> (u8, f32) {
+        (a + b, x * 2.0)
+    }
+
+    #[unsafe_math(int)]
+    fn int_only_abs(a: i32) -> i32 {
+        a.abs()
+    }
+
+    #[unsafe_math(float)]
+    fn float_only_abs(a: i32) -> i32 {
+        a.abs()
+    }
+
+    #[unsafe_math(types(f32, u32), float(nnan))]
+    fn listed_types(a: u32, b: u64, x: f32, y: f64) -> (u32, u64, f32, f64) {
+        (a * 2, b * 2, -x + 1.0, -y + 1.0)
+    }
+
+    #[unsafe_math(int, float(nnan))]
+    fn int_only_derived(a: Body, b: Pixel) -> (Body, Pixel) {
+        (a + a, b << 1u8)
+    }
+
+    #[unsafe_math(float, float(nnan))]
+    fn float_only_vek(a: Vec2<f32>, b: Vec2<u8>) -> (Vec2<f32>, Vec2<u8>) {
+        (a * 2.0, b + b)
+    }
+
+    #[test]
+    fn test_type_filters() {
+        assert_eq!(int_only(1, 2, 1.5), (3, 3.0));
+        assert_eq!(float_only(1, 2, 1.5), (3, 3.0));
+        assert_eq!(int_only_abs(-3), 3);
+        assert_eq!(float_only_abs(-3), 3);
+        assert_eq!(listed_types(1, 2, 1.5, 2.5), (2, 4, -0.5, -1.5));
+
+        let (_, nan) = int_only(1, 2, f32::NAN);
+        assert!(nan.is_nan());
+        let (_, _, _, nan) = listed_types(1, 2, 1.5, f64::NAN);
+        assert!(nan.is_nan());
+        let body = Body { pos: Vec3::one(), mass: f32::NAN };
+        let (sum, shifted) = int_only_derived(body, Pixel(1, 2, 3));
+        assert!(sum.mass.is_nan());
+        assert_eq!(shifted, Pixel(2, 4, 6));
+        assert_eq!(float_only_vek(Vec2::one(), Vec2::new(1, 2)), (Vec2::new(2.0, 2.0), Vec2::new(2, 4)));
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "`a + b`")]
+    fn test_int_only_rewrites_ints() {
+        int_only(255, 1, 1.0);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "attempt to add with overflow")]
+    fn test_float_only_keeps_int_ops() {
+        float_only(255, 1, 1.0);
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "`x * 2.0`")]
+    fn test_float_only_rewrites_floats() {
+        float_only(1, 1, f32::NAN);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "attempt to negate with overflow")]
+    fn test_float_only_keeps_int_methods() {
+        float_only_abs(i32::MIN);
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "`a.abs()`")]
+    fn test_int_only_rewrites_int_methods() {
+        int_only_abs(i32::MIN);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "attempt to multiply with overflow")]
+    fn test_listed_types_keep_others() {
+        listed_types(1, u64::MAX, 1.0, 1.0);
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "`a * 2`")]
+    fn test_listed_types_rewrite_listed() {
+        listed_types(u32::MAX, 1, 1.0, 1.0);
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "`a * 2.0`")]
+    fn test_float_only_rewrites_vek() {
+        float_only_vek(Vec2::new(f32::NAN, 1.0), Vec2::zero());
+    }
+
+    // vars(...), only(...) and shallow: arithmetic outside of selection keeps plain operators
+
+    #[unsafe_math(vars(i, stride))]
+    fn strided(data: &[u8], i: usize, stride: usize, a: u8, b: u8) -> (u8, u8) {
+        (data[i * stride + 1], a + b)
+    }
+
+    #[unsafe_math(only(index, range_bounds))]
+    fn shifted_sum(data: &[u32], n: usize, a: u32, b: u32) -> (u32, u32) {
+        let mut sum = 0;
+        for i in 0..n - 1 {
+            sum += data[i + 1];
+        }
+        (sum, a + b)
+    }
+
+    #[unsafe_math(only(index))]
+    fn previous(data: &[u8], i: usize) -> u8 {
+        data[i - 1]
+    }
+
+    #[unsafe_math(shallow)]
+    fn shallow(a: u8, b: u8) -> (u8, u8, u8) {
+        fn nested(a: u8, b: u8) -> u8 {
+            a + b
+        }
+        let closure = |a: u8, b: u8| a * b;
+        (a - b, nested(a, b), closure(a, b))
+    }
+
+    #[test]
+    fn test_selection() {
+        assert_eq!(strided(&[1, 2, 3, 4], 1, 2, 1, 2), (4, 3));
+        assert_eq!(shifted_sum(&[1, 2, 3], 3, 1, 2), (5, 3));
+        assert_eq!(previous(&[1, 2], 1), 1);
+        assert_eq!(shallow(3, 2), (1, 5, 6));
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "`i * stride`")]
+    fn test_vars_rewrite_mentioning() {
+        strided(&[0], usize::MAX, 2, 1, 1);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "attempt to add with overflow")]
+    fn test_vars_keep_others() {
+        strided(&[0, 0], 0, 0, 255, 1);
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "`n - 1`")]
+    fn test_only_rewrites_range_bounds() {
+        shifted_sum(&[], 0, 1, 1);
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "`i - 1`")]
+    fn test_only_rewrites_subscripts() {
+        previous(&[1, 2], 0);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "attempt to add with overflow")]
+    fn test_only_keeps_others() {
+        shifted_sum(&[1, 2], 2, u32::MAX, 1);
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "`a - b`")]
+    fn test_shallow_rewrites_own_body() {
+        shallow(0, 1);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "attempt to add with overflow")]
+    fn test_shallow_keeps_nested_fn() {
+        shallow(255, 1);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "attempt to multiply with overflow")]
+    fn test_shallow_keeps_closures() {
+        shallow(16, 16);
+    }
+
+    // assume(...): asserted in debug and checked builds, told to the optimizer otherwise
+
+    #[unsafe_math(assume(d != 0, n < 4096))]
+    fn bucket(n: u32, d: u32) -> u32 {
+        n / d
+    }
+
+    #[unsafe_math(index, assume(a.len() == b.len()))]
+    fn dot(a: &[i32], b: &[i32]) -> i32 {
+        let mut sum = 0;
+        for i in 0..a.len() {
+            sum += a[i] * b[i];
+        }
+        sum
+    }
+
+    #[unsafe_math]
+    fn share(total: u32, parts: u32) -> u32 {
+        let scaled = total * 2;
+        #[unsafe_math(assume(parts > 0))]
+        {
+            scaled / parts
+        }
+    }
+
+    fn share_block(total: u32, parts: u32) -> u32 {
+        unsafe_math_block! {
+            #![unsafe_math(assume(parts > 0))]
+            total * 2 / parts
+        }
+    }
+
+    #[test]
+    fn test_assume() {
+        assert_eq!(bucket(4095, 2), 2047);
+        assert_eq!(dot(&[1, 2], &[3, 4]), 11);
+        assert_eq!(share(3, 2), 3);
+        assert_eq!(share_block(3, 2), 3);
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "assumption `d != 0`")]
+    fn test_assume_checked() {
+        bucket(1, 0);
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "assumption `n < 4096`")]
+    fn test_assume_checks_every_condition() {
+        bucket(4096, 1);
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "assumption `a.len() == b.len()`")]
+    fn test_assume_lengths() {
+        dot(&[1, 2], &[3]);
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "assumption `parts > 0`")]
+    fn test_assume_nested_block() {
+        share(1, 0);
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, unsafe_math_checked))]
+    #[should_panic(expected = "assumption `parts > 0`")]
+    fn test_assume_macro_block() {
+        share_block(1, 0);
+    }
 }
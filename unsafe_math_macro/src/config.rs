@@ -0,0 +1,455 @@
+//! Parsing of `#[unsafe_math(...)]` arguments
+//!
+//! Same syntax is used by the attribute and by the leading `#![unsafe_math(...)]` of `unsafe_math_block!`
+
+use proc_macro2::Ident;
+use syn::{meta::ParseNestedMeta, parse::Parse, punctuated::Punctuated, BinOp, Expr, Path, Token};
+
+/// Operators that macro knows how to rewrite
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Shl,
+    Shr,
+    Neg,
+    /// Numeric method calls, `x.pow(3)`, `v.abs()`...
+    Methods,
+}
+
+impl Op {
+    pub const ALL: [Op; 9] = [
+        Op::Add,
+        Op::Sub,
+        Op::Mul,
+        Op::Div,
+        Op::Rem,
+        Op::Shl,
+        Op::Shr,
+        Op::Neg,
+        Op::Methods,
+    ];
+
+    /// Maps binary operator (plain or compound-assign) to the corresponding Op, if any.
+    pub fn from_bin_op(op: &BinOp) -> Option<Op> {
+        Some(match op {
+            BinOp::Add(_) | BinOp::AddAssign(_) => Op::Add,
+            BinOp::Sub(_) | BinOp::SubAssign(_) => Op::Sub,
+            BinOp::Mul(_) | BinOp::MulAssign(_) => Op::Mul,
+            BinOp::Div(_) | BinOp::DivAssign(_) => Op::Div,
+            BinOp::Rem(_) | BinOp::RemAssign(_) => Op::Rem,
+            BinOp::Shl(_) | BinOp::ShlAssign(_) => Op::Shl,
+            BinOp::Shr(_) | BinOp::ShrAssign(_) => Op::Shr,
+            _ => return None,
+        })
+    }
+
+    /// Name used in attribute arguments, e.g. `#[unsafe_math(add)]`.
+    /// For operators, also the name of dispatching function in `unsafe_math::__private`
+    pub fn name(self) -> &'static str {
+        match self {
+            Op::Add => "add",
+            Op::Sub => "sub",
+            Op::Mul => "mul",
+            Op::Div => "div",
+            Op::Rem => "rem",
+            Op::Shl => "shl",
+            Op::Shr => "shr",
+            Op::Neg => "neg",
+            Op::Methods => "methods",
+        }
+    }
+
+    fn bit(self) -> u16 {
+        1 << self as u8
+    }
+}
+
+/// Set of operators, tiny bitset
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct Ops(u16);
+
+impl Ops {
+    pub fn all() -> Self {
+        Op::ALL.into_iter().fold(Ops::default(), |ops, op| ops.with(op))
+    }
+
+    pub fn contains(self, op: Op) -> bool {
+        self.0 & op.bit() != 0
+    }
+
+    pub fn with(self, op: Op) -> Self {
+        Ops(self.0 | op.bit())
+    }
+
+    pub fn without(self, op: Op) -> Self {
+        Ops(self.0 & !op.bit())
+    }
+}
+
+/// Arithmetic semantics, `policy = ...`
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum Policy {
+    /// Fast operations, UB is checked only in debug builds
+    #[default]
+    Unchecked,
+    Wrapping,
+    Saturating,
+    /// Fast operations, UB always panics
+    Checked,
+    /// Fast operations, UB always aborts
+    Trap,
+}
+
+impl Policy {
+    const ALL: [Policy; 5] = [
+        Policy::Unchecked,
+        Policy::Wrapping,
+        Policy::Saturating,
+        Policy::Checked,
+        Policy::Trap,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Policy::Unchecked => "unchecked",
+            Policy::Wrapping => "wrapping",
+            Policy::Saturating => "saturating",
+            Policy::Checked => "checked",
+            Policy::Trap => "trap",
+        }
+    }
+
+    /// Wrapping and saturating operators have their own traits, with no UB to check
+    pub fn is_fast(self) -> bool {
+        !matches!(self, Policy::Wrapping | Policy::Saturating)
+    }
+}
+
+/// Syntactic contexts of `only(...)`
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Context {
+    /// Subscripts, `i * stride + j` of `data[i * stride + j]`
+    Index,
+    /// Start and end of ranges, `n - 1` of `0..n - 1`
+    RangeBounds,
+}
+
+impl Context {
+    const ALL: [Context; 2] = [Context::Index, Context::RangeBounds];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Context::Index => "index",
+            Context::RangeBounds => "range_bounds",
+        }
+    }
+}
+
+/// Float fast-math flags of `float(...)`, names and paths of `FloatFlags` constants.
+/// Operations only have two sets of them, `FloatFlags::algebraic` tells which one a set maps to
+pub const FLOAT_FLAGS: [(&str, &str); 7] = [
+    ("reassoc", "REASSOC"),
+    ("contract", "CONTRACT"),
+    ("nnan", "NNAN"),
+    ("ninf", "NINF"),
+    ("nsz", "NSZ"),
+    ("arcp", "ARCP"),
+    ("afn", "AFN"),
+];
+
+/// Value-changing flags of `float(algebraic)`
+const ALGEBRAIC_FLAGS: [&str; 5] = ["REASSOC", "CONTRACT", "NSZ", "ARCP", "AFN"];
+
+/// Types of `types(...)`, names and paths of `Types` constants
+pub const TYPES: [(&str, &str); 14] = [
+    ("i8", "I8"),
+    ("u8", "U8"),
+    ("i16", "I16"),
+    ("u16", "U16"),
+    ("i32", "I32"),
+    ("u32", "U32"),
+    ("i64", "I64"),
+    ("u64", "U64"),
+    ("i128", "I128"),
+    ("u128", "U128"),
+    ("isize", "ISIZE"),
+    ("usize", "USIZE"),
+    ("f32", "F32"),
+    ("f64", "F64"),
+];
+
+/// What macro is allowed to rewrite, and how
+#[derive(Clone)]
+pub struct Config {
+    pub ops: Ops,
+    /// Path to `unsafe_math` crate in generated code, `::unsafe_math` unless overridden with `crate = ...`
+    pub krate: Path,
+    pub macros: MacroFilter,
+    /// Indexing without bounds checks, opt-in with `index`
+    pub index: bool,
+    /// Unchecked `as` casts, opt-in with `cast`
+    pub cast: bool,
+    pub policy: Policy,
+    /// `FloatFlags` constants of `float(...)`, `FloatFlags::ALGEBRAIC` if `None`
+    pub float: Option<Vec<Ident>>,
+    /// `Types` constants of `int`, `float` and `types(...)`, every type if `None`
+    pub types: Option<Vec<Ident>>,
+    /// Arithmetic is rewritten only if it mentions one of these variables, `vars(...)`
+    pub vars: Option<Vec<Ident>>,
+    /// Arithmetic is rewritten only inside these contexts, `only(...)`
+    pub only: Option<Vec<Context>>,
+    /// Closures and nested fn items are left as they are
+    pub shallow: bool,
+    /// Conditions of `assume(...)`, asserted at the top of the fn or block
+    pub assume: Vec<Expr>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            ops: Ops::all(),
+            krate: syn::parse_quote!(::unsafe_math),
+            macros: MacroFilter::default(),
+            index: false,
+            cast: false,
+            policy: Policy::Unchecked,
+            float: None,
+            types: None,
+            vars: None,
+            only: None,
+            shallow: false,
+            assume: Vec::new(),
+        }
+    }
+}
+
+/// Macros that use the text of their arguments, left alone unless listed in `macros(...)`
+const TEXT_MACROS: [&str; 2] = ["stringify", "concat"];
+
+/// Macros that take patterns, which can't be rewritten, always left alone
+const PATTERN_MACROS: [&str; 3] = ["matches", "assert_matches", "debug_assert_matches"];
+
+/// Which macro invocations get their arguments rewritten, by last segment of macro path.
+/// Everything but [`TEXT_MACROS`] and [`PATTERN_MACROS`] by default, `macros(vec, assert_eq)` allows only listed,
+/// `macros(not(dbg))` denies listed
+#[derive(Clone, Default)]
+pub struct MacroFilter {
+    allowed: Option<Vec<Ident>>,
+    denied: Vec<Ident>,
+}
+
+impl MacroFilter {
+    pub fn allows(&self, name: &Ident) -> bool {
+        let listed = match &self.allowed {
+            Some(allowed) => allowed.contains(name),
+            None => !TEXT_MACROS.iter().any(|text| name == text),
+        };
+        listed && !self.denied.contains(name) && !PATTERN_MACROS.iter().any(|pattern| name == pattern)
+    }
+
+    fn parse_meta(&mut self, meta: ParseNestedMeta) -> syn::Result<()> {
+        if meta.path.is_ident("not") {
+            return meta.parse_nested_meta(|inner| {
+                self.denied.push(inner.path.require_ident()?.clone());
+                Ok(())
+            });
+        }
+        let name = meta.path.require_ident()?.clone();
+        self.allowed.get_or_insert_with(Vec::new).push(name);
+        Ok(())
+    }
+}
+
+/// Accumulates arguments while they are being parsed.
+/// Explicitly listed operators replace the default "everything", `not(...)` is subtracted afterwards.
+#[derive(Default)]
+pub struct ConfigBuilder {
+    included: Option<Ops>,
+    excluded: Ops,
+    krate: Option<Path>,
+    macros: MacroFilter,
+    index: bool,
+    cast: bool,
+    policy: Policy,
+    float: Option<Vec<Ident>>,
+    types: Option<Vec<Ident>>,
+    vars: Option<Vec<Ident>>,
+    only: Option<Vec<Context>>,
+    shallow: bool,
+    assume: Vec<Expr>,
+}
+
+impl ConfigBuilder {
+    /// Parses one argument. Meant to be used with [`syn::meta::parser`] / [`syn::Attribute::parse_nested_meta`].
+    pub fn parse_meta(&mut self, meta: ParseNestedMeta) -> syn::Result<()> {
+        if meta.path.is_ident("crate") {
+            self.krate = Some(meta.value()?.parse()?);
+            return Ok(());
+        }
+        // not operators, so they don't replace the default set
+        if meta.path.is_ident("index") {
+            self.index = true;
+            return Ok(());
+        }
+        if meta.path.is_ident("cast") {
+            self.cast = true;
+            return Ok(());
+        }
+        if meta.path.is_ident("shallow") {
+            self.shallow = true;
+            return Ok(());
+        }
+        if meta.path.is_ident("assume") {
+            let content;
+            syn::parenthesized!(content in meta.input);
+            self.assume.extend(Punctuated::<Expr, Token![,]>::parse_terminated(&content)?);
+            return Ok(());
+        }
+        if meta.path.is_ident("vars") {
+            let vars = self.vars.get_or_insert_with(Vec::new);
+            return meta.parse_nested_meta(|inner| {
+                vars.push(inner.path.require_ident()?.clone());
+                Ok(())
+            });
+        }
+        if meta.path.is_ident("only") {
+            let only = self.only.get_or_insert_with(Vec::new);
+            return meta.parse_nested_meta(|inner| {
+                let context = Context::ALL
+                    .into_iter()
+                    .find(|context| inner.path.is_ident(context.name()))
+                    .ok_or_else(|| {
+                        let names: Vec<_> = Context::ALL.iter().map(|context| context.name()).collect();
+                        inner.error(format!("unknown context, expected one of: {}", names.join(", ")))
+                    })?;
+                only.push(context);
+                Ok(())
+            });
+        }
+        if meta.path.is_ident("policy") {
+            let name: Ident = meta.value()?.parse()?;
+            self.policy = Policy::ALL.into_iter().find(|policy| name == policy.name()).ok_or_else(|| {
+                let names: Vec<_> = Policy::ALL.iter().map(|policy| policy.name()).collect();
+                syn::Error::new_spanned(
+                    &name,
+                    format!("unknown unsafe_math policy, expected one of: {}", names.join(", ")),
+                )
+            })?;
+            return self.check_types(&meta);
+        }
+        // bare `float` is a type filter, `float(...)` are flags
+        if meta.path.is_ident("int") || (meta.path.is_ident("float") && !meta.input.peek(syn::token::Paren)) {
+            let constant = if meta.path.is_ident("int") { "INT" } else { "FLOAT" };
+            let span = meta.path.require_ident()?.span();
+            self.types.get_or_insert_with(Vec::new).push(Ident::new(constant, span));
+            return self.check_types(&meta);
+        }
+        if meta.path.is_ident("types") {
+            let types = self.types.get_or_insert_with(Vec::new);
+            meta.parse_nested_meta(|inner| {
+                let name = inner.path.require_ident()?;
+                let (_, constant) = TYPES.into_iter().find(|(ty, _)| name == ty).ok_or_else(|| {
+                    let names: Vec<_> = TYPES.iter().map(|(ty, _)| *ty).collect();
+                    inner.error(format!("unknown type, expected one of: {}", names.join(", ")))
+                })?;
+                types.push(Ident::new(constant, name.span()));
+                Ok(())
+            })?;
+            return self.check_types(&meta);
+        }
+        if meta.path.is_ident("float") {
+            // parsed by hand, `float()` (no flags at all) is valid
+            let content;
+            syn::parenthesized!(content in meta.input);
+            let flags = self.float.get_or_insert_with(Vec::new);
+            for name in content.parse_terminated(Ident::parse, Token![,])? {
+                // every flag, including UB-on-NaN ones
+                if name == "all" {
+                    flags.extend(FLOAT_FLAGS.iter().map(|(_, constant)| Ident::new(constant, name.span())));
+                    continue;
+                }
+                if name == "algebraic" {
+                    flags.extend(ALGEBRAIC_FLAGS.iter().map(|constant| Ident::new(constant, name.span())));
+                    continue;
+                }
+                let (_, constant) = FLOAT_FLAGS.into_iter().find(|(flag, _)| name == flag).ok_or_else(|| {
+                    let names: Vec<_> = FLOAT_FLAGS.iter().map(|(flag, _)| *flag).collect();
+                    syn::Error::new_spanned(
+                        &name,
+                        format!("unknown float flag, expected `all`, `algebraic` or one of: {}", names.join(", ")),
+                    )
+                })?;
+                flags.push(Ident::new(constant, name.span()));
+            }
+            return Ok(());
+        }
+        if meta.path.is_ident("macros") {
+            return meta.parse_nested_meta(|inner| self.macros.parse_meta(inner));
+        }
+        if meta.path.is_ident("not") {
+            return meta.parse_nested_meta(|inner| {
+                let op = parse_op(&inner)?;
+                self.excluded = self.excluded.with(op);
+                Ok(())
+            });
+        }
+        let op = parse_op(&meta)?;
+        self.included = Some(self.included.unwrap_or_default().with(op));
+        Ok(())
+    }
+
+    /// Wrapping and saturating operators are for integers of the same type anyway, and have no per-type versions
+    fn check_types(&self, meta: &ParseNestedMeta) -> syn::Result<()> {
+        if self.types.is_some() && !self.policy.is_fast() {
+            return Err(meta.error(format!(
+                "`int`, `float` and `types(...)` can't be used with `policy = {}`",
+                self.policy.name()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Config of `#[unsafe_math(...)]` nested in another one's scope, crate path is inherited
+    pub fn build_nested(mut self, outer: &Config) -> Config {
+        self.krate.get_or_insert_with(|| outer.krate.clone());
+        self.build()
+    }
+
+    pub fn build(self) -> Config {
+        let ops = Op::ALL
+            .into_iter()
+            .filter(|&op| self.excluded.contains(op))
+            .fold(self.included.unwrap_or_else(Ops::all), Ops::without);
+        Config {
+            ops,
+            krate: self.krate.unwrap_or_else(|| Config::default().krate),
+            macros: self.macros,
+            index: self.index,
+            cast: self.cast,
+            policy: self.policy,
+            float: self.float,
+            types: self.types,
+            vars: self.vars,
+            only: self.only,
+            shallow: self.shallow,
+            assume: self.assume,
+        }
+    }
+}
+
+fn parse_op(meta: &ParseNestedMeta) -> syn::Result<Op> {
+    Op::ALL
+        .into_iter()
+        .find(|op| meta.path.is_ident(op.name()))
+        .ok_or_else(|| {
+            let names: Vec<_> = Op::ALL.iter().map(|op| op.name()).collect();
+            meta.error(format!(
+                "unknown unsafe_math operator, expected one of: {}",
+                names.join(", ")
+            ))
+        })
+}
@@ -0,0 +1,230 @@
+//! `#[derive(UnsafeMath)]` for user structs
+//!
+//! Field-wise by default, same as vek types get it inside `unsafe_math_trait`.
+//! `#[unsafe_math(via_ops)]` forwards to the type's own `std::ops` impls instead
+//!
+//! Every operator gets its own impl (`FastAdd`, `FastShl`...), bounded on what it needs from fields or from the type.
+//! Which operators are derived is picked by `#[unsafe_math(ops(add, mul, ...))]`. Without it, arithmetic ones are,
+//! and field-wise shifts too when all fields are integers (or generic, then shifts are bounded on them)
+//!
+//! Field-wise structs get scalars (for `int`, `float` and `types(...)`) of their fields. `via_ops` ones don't have any,
+//! their fields may be of any type. These are never fast under a types filter, which changes nothing,
+//! since their fast operators are the plain ones.
+
+use proc_macro2::{Ident, TokenStream, TokenTree};
+use quote::{format_ident, quote};
+use syn::{parse_quote, Data, DataStruct, DeriveInput, Generics, Member, Path, Type, WherePredicate};
+
+pub fn derive_unsafe_math(input: DeriveInput) -> syn::Result<TokenStream> {
+    let mut via_ops = false;
+    let mut ops: Option<Vec<&str>> = None;
+    let mut krate: Path = parse_quote!(::unsafe_math);
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("unsafe_math")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("via_ops") {
+                via_ops = true;
+                return Ok(());
+            }
+            if meta.path.is_ident("ops") {
+                let ops = ops.get_or_insert_with(Vec::new);
+                return meta.parse_nested_meta(|inner| {
+                    match OPS.iter().find(|(op, _)| inner.path.is_ident(op)) {
+                        Some((op, _)) => ops.push(*op),
+                        None => return Err(inner.error("unknown operator, expected add, sub, mul, div, rem, shl or shr")),
+                    }
+                    Ok(())
+                });
+            }
+            if meta.path.is_ident("crate") {
+                krate = meta.value()?.parse()?;
+                return Ok(());
+            }
+            Err(meta.error("unknown unsafe_math derive option, expected `via_ops`, `ops(...)` or `crate = ...`"))
+        })?;
+    }
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "UnsafeMath can only be derived for structs",
+        ));
+    };
+
+    // shifts of `f32` fields or of a type without `Shl<u32>` don't exist, so these are opt-in unless fields are integers
+    let shifts = !via_ops && data.fields.iter().all(|field| is_integer(&field.ty) || is_generic(&field.ty, &input.generics));
+    let selected = |op: &str, is_shift: bool| match &ops {
+        Some(ops) => ops.contains(&op),
+        None => !is_shift || shifts,
+    };
+
+    let name = &input.ident;
+    let impls = OPS.iter().filter(|&&(op, is_shift)| selected(op, is_shift)).map(|&(op, is_shift)| {
+        let trait_ = format_ident!("Fast{}", camel(op));
+        let (predicates, fns) = if via_ops {
+            via_ops_impl(&krate, op, is_shift)
+        } else {
+            field_wise_impl(&krate, data, &trait_, op, is_shift)
+        };
+        let generics = with_predicates(&input.generics, predicates);
+        let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+        quote! {
+            impl #impl_generics #krate::#trait_ for #name #ty_generics #where_clause {
+                type Output = Self;
+                #fns
+            }
+        }
+    });
+    let scalars = (!via_ops).then(|| scalars_impl(&krate, &input, data));
+    Ok(quote!(#(#impls)* #scalars))
+}
+
+fn scalars_impl(krate: &Path, input: &DeriveInput, data: &DataStruct) -> TokenStream {
+    let name = &input.ident;
+    let types: Vec<_> = data.fields.iter().map(|field| &field.ty).collect();
+    let predicates = types
+        .iter()
+        .map(|ty| parse_quote!(#ty: #krate::__private::Scalars))
+        .collect();
+    let generics = with_predicates(&input.generics, predicates);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    quote! {
+        impl #impl_generics #krate::__private::Scalars for #name #ty_generics #where_clause {
+            const TYPES: #krate::__private::Types =
+                #krate::__private::Types::NONE #(.union(<#types as #krate::__private::Scalars>::TYPES))*;
+        }
+    }
+}
+
+/// Integer primitive, by name
+fn is_integer(ty: &Type) -> bool {
+    const INTEGERS: [&str; 12] = [
+        "i8", "u8", "i16", "u16", "i32", "u32", "i64", "u64", "i128", "u128", "isize", "usize",
+    ];
+    matches!(ty, Type::Path(path) if path.qself.is_none() && INTEGERS.iter().any(|int| path.path.is_ident(int)))
+}
+
+/// Mentions a type parameter of the struct, so its bounds are checked for each use rather than at the impl
+fn is_generic(ty: &Type, generics: &Generics) -> bool {
+    fn mentions(tokens: TokenStream, generics: &Generics) -> bool {
+        tokens.into_iter().any(|token| match token {
+            TokenTree::Ident(ident) => generics.type_params().any(|param| param.ident == ident),
+            TokenTree::Group(group) => mentions(group.stream(), generics),
+            _ => false,
+        })
+    }
+    mentions(quote!(#ty), generics)
+}
+
+/// Operator name and whether it is a shift
+const OPS: [(&str, bool); 7] = [
+    ("add", false),
+    ("sub", false),
+    ("mul", false),
+    ("div", false),
+    ("rem", false),
+    ("shl", true),
+    ("shr", true),
+];
+
+fn field_wise_impl(
+    krate: &Path,
+    data: &DataStruct,
+    trait_: &Ident,
+    op: &str,
+    is_shift: bool,
+) -> (Vec<WherePredicate>, TokenStream) {
+    let fast = format_ident!("fast_{op}");
+    let try_fast = format_ident!("try_fast_{op}");
+    let fast_with = format_ident!("fast_{op}_with");
+    let try_fast_with = format_ident!("try_fast_{op}_with");
+    let members: Vec<Member> = data.fields.members().collect();
+    let predicates = data
+        .fields
+        .iter()
+        .map(|field| {
+            let ty = &field.ty;
+            parse_quote!(#ty: #krate::#trait_<Output = #ty>)
+        })
+        .collect();
+    let fns = if is_shift {
+        quote! {
+            #[inline(always)]
+            fn #fast<R: #krate::ShiftAmount>(self, rhs: R) -> Self {
+                Self { #( #members: #krate::#trait_::#fast(self.#members, rhs) ),* }
+            }
+            #[inline(always)]
+            fn #try_fast<R: #krate::ShiftAmount>(self, rhs: R) -> Option<Self> {
+                Some(Self { #( #members: #krate::#trait_::#try_fast(self.#members, rhs)? ),* })
+            }
+        }
+    } else {
+        quote! {
+            #[inline(always)]
+            fn #fast(self, rhs: Self) -> Self {
+                Self { #( #members: #krate::#trait_::#fast(self.#members, rhs.#members) ),* }
+            }
+            #[inline(always)]
+            fn #try_fast(self, rhs: Self) -> Option<Self> {
+                Some(Self { #( #members: #krate::#trait_::#try_fast(self.#members, rhs.#members)? ),* })
+            }
+            // float fields get the flags of `float(...)`
+            #[inline(always)]
+            fn #fast_with(self, rhs: Self, flags: #krate::FloatFlags) -> Self {
+                Self { #( #members: #krate::#trait_::#fast_with(self.#members, rhs.#members, flags) ),* }
+            }
+            #[inline(always)]
+            fn #try_fast_with(self, rhs: Self, flags: #krate::FloatFlags) -> Option<Self> {
+                Some(Self { #( #members: #krate::#trait_::#try_fast_with(self.#members, rhs.#members, flags)? ),* })
+            }
+        }
+    };
+    (predicates, fns)
+}
+
+/// Plain operators of the type, so fast and checked versions are the same.
+/// Shifts go through `Shl<u32>`/`Shr<u32>`. `try_*` return `None` only for shift amounts that don't fit `u32`:
+/// checked policy can't see inside the operators, they do their own checks
+fn via_ops_impl(krate: &Path, op: &str, is_shift: bool) -> (Vec<WherePredicate>, TokenStream) {
+    let fast = format_ident!("fast_{op}");
+    let try_fast = format_ident!("try_fast_{op}");
+    let std_trait = format_ident!("{}", camel(op));
+    let method = format_ident!("{op}");
+    if is_shift {
+        let predicate = parse_quote!(Self: ::core::ops::#std_trait<u32, Output = Self>);
+        let fns = quote! {
+            #[inline(always)]
+            fn #fast<R: #krate::ShiftAmount>(self, rhs: R) -> Self {
+                ::core::ops::#std_trait::#method(self, #krate::__private::shift_amount(rhs))
+            }
+            #[inline(always)]
+            fn #try_fast<R: #krate::ShiftAmount>(self, rhs: R) -> Option<Self> {
+                Some(::core::ops::#std_trait::#method(self, #krate::ShiftAmount::shift_amount(rhs)?))
+            }
+        };
+        (vec![predicate], fns)
+    } else {
+        let predicate = parse_quote!(Self: ::core::ops::#std_trait<Output = Self>);
+        let fns = quote! {
+            #[inline(always)]
+            fn #fast(self, rhs: Self) -> Self {
+                ::core::ops::#std_trait::#method(self, rhs)
+            }
+            #[inline(always)]
+            fn #try_fast(self, rhs: Self) -> Option<Self> {
+                Some(::core::ops::#std_trait::#method(self, rhs))
+            }
+        };
+        (vec![predicate], fns)
+    }
+}
+
+fn with_predicates(generics: &Generics, predicates: Vec<WherePredicate>) -> Generics {
+    let mut generics = generics.clone();
+    generics.make_where_clause().predicates.extend(predicates);
+    generics
+}
+
+fn camel(op: &str) -> String {
+    let mut chars = op.chars();
+    let first = chars.next().unwrap().to_ascii_uppercase();
+    format!("{first}{}", chars.as_str())
+}
@@ -1,75 +1,792 @@
 //! # unsafe_math proc macro
 //!
 //! This crate contains the proc macro implementation for `unsafe_math`
-//! The macro replaces binary operations with calls to "fast" trait methods
+//! The macro replaces binary operations (and unary minus) with calls to "fast" trait methods
+//! (through `unsafe_math::__private`, which picks checked versions in debug builds)
 
+mod config;
+mod derive;
+mod macros;
+mod source;
+
+use config::{Config, ConfigBuilder, Context, Op, Policy};
+use macros::MacroArgs;
+use source::source_text;
 use proc_macro::TokenStream;
 use proc_macro2::{Ident, Span};
-use quote::quote;
+use quote::{quote, quote_spanned, ToTokens};
 use syn::{
     parse::{Parse, ParseStream},
     parse_macro_input,
+    spanned::Spanned,
+    visit::Visit,
     visit_mut::{self, VisitMut},
-    BinOp, Expr, Stmt, Token,
+    Attribute, BinOp, Expr, ExprBinary, ExprCast, ExprGroup, ExprIndex, ExprMethodCall, ExprParen, ExprReference, ExprUnary,
+    GenericArgument, Item, Macro, Stmt, Token, Type, UnOp,
 };
 
-struct UnsafeMathVisitor;
+struct UnsafeMathVisitor {
+    config: Config,
+    /// How the expression being visited is used, matters for indexing
+    access: Access,
+    /// Inside `#[safe_math]` or `safe_math!`: nothing is rewritten until a nested `#[unsafe_math]`
+    safe: bool,
+    /// Subscripts and range bounds the expression being visited is part of, for `only(...)`
+    contexts: Vec<Context>,
+}
+
+/// Scope started by an attribute inside macro's scope
+enum Scope {
+    /// `#[unsafe_math(...)]`, rewrites with its own config
+    Unsafe(Box<Config>),
+    /// `#[safe_math]`, keeps operators as they are
+    Safe,
+}
+
+/// Whether place expression is read from or written to.
+/// Macro only sees syntax, so for some places (receivers of arbitrary methods) it can't tell
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Access {
+    Read,
+    Write,
+    Unknown,
+}
+
+impl UnsafeMathVisitor {
+    fn new(config: Config) -> Self {
+        UnsafeMathVisitor {
+            config,
+            access: Access::Read,
+            safe: false,
+            contexts: Vec::new(),
+        }
+    }
+
+    fn visit_place_mut(&mut self, expr: &mut Expr, access: Access) {
+        self.access = access;
+        self.visit_expr_mut(expr);
+    }
+
+    /// Takes `#[unsafe_math(...)]` or `#[safe_math]` off a statement or expression inside macro's scope.
+    /// Handled here rather than left for the compiler, which would require `stmt_expr_attributes` and `proc_macro_hygiene`.
+    /// Attributes with invalid arguments are left in place, so that their own expansion reports the error.
+    /// Same for `assume(...)` on anything but a block, there is nowhere to put the conditions
+    fn nested_scope(&self, attrs: &mut Vec<Attribute>, block: bool) -> Option<Scope> {
+        let position = attrs.iter().position(|attr| attr_named(attr, "unsafe_math"));
+        if let Some(position) = position {
+            let mut builder = ConfigBuilder::default();
+            if !matches!(attrs[position].meta, syn::Meta::Path(_)) {
+                attrs[position].parse_nested_meta(|meta| builder.parse_meta(meta)).ok()?;
+            }
+            let config = builder.build_nested(&self.config);
+            if !(block || config.assume.is_empty()) {
+                return None;
+            }
+            attrs.remove(position);
+            return Some(Scope::Unsafe(Box::new(config)));
+        }
+        let position = attrs
+            .iter()
+            .position(|attr| attr_named(attr, "safe_math") && matches!(attr.meta, syn::Meta::Path(_)))?;
+        attrs.remove(position);
+        Some(Scope::Safe)
+    }
+
+    fn enter(&mut self, scope: Option<Scope>, visit: impl FnOnce(&mut Self)) {
+        match scope {
+            Some(Scope::Unsafe(config)) => {
+                let outer = std::mem::replace(&mut self.config, *config);
+                self.with_safe(false, visit);
+                self.config = outer;
+            }
+            Some(Scope::Safe) => self.with_safe(true, visit),
+            None => visit(self),
+        }
+    }
+
+    fn with_safe(&mut self, safe: bool, visit: impl FnOnce(&mut Self)) {
+        let outer = std::mem::replace(&mut self.safe, safe);
+        visit(self);
+        self.safe = outer;
+    }
+
+    fn in_context(&mut self, context: Context, visit: impl FnOnce(&mut Self)) {
+        self.contexts.push(context);
+        visit(self);
+        self.contexts.pop();
+    }
+
+    /// `assume(...)` conditions as statements for the top of a fn or block. Conditions themselves are not rewritten
+    fn assumptions(&self) -> Vec<Stmt> {
+        let mode = self.mode();
+        (self.config.assume.iter())
+            .map(|condition| {
+                let source = source_text(condition.to_token_stream());
+                // spanned, so that #[track_caller] in checked mode points at the condition
+                let span = condition.span();
+                let assume = Ident::new("assume", span);
+                syn::parse_quote_spanned! {span=>
+                    #mode.#assume(#condition, #source);
+                }
+            })
+            .collect()
+    }
+
+    /// Whether `vars(...)` and `only(...)` let arithmetic expression be rewritten at this point.
+    /// Indexing is not arithmetic, `index` alone decides about it
+    fn selected(&self, expr: &Expr) -> bool {
+        let in_context = (self.config.only.as_ref())
+            .is_none_or(|only| self.contexts.iter().any(|context| only.contains(context)));
+        in_context && (self.config.vars.as_ref()).is_none_or(|vars| mentions(expr, vars))
+    }
+}
+
+/// Whether expression refers to one of the variables anywhere inside, `i` in `(i + 1) * stride`
+fn mentions(expr: &Expr, vars: &[Ident]) -> bool {
+    struct Mentions<'a> {
+        vars: &'a [Ident],
+        found: bool,
+    }
+
+    impl<'ast> Visit<'ast> for Mentions<'_> {
+        fn visit_expr_path(&mut self, path: &'ast syn::ExprPath) {
+            if let Some(ident) = path.path.get_ident() {
+                self.found |= self.vars.contains(ident);
+            }
+        }
+    }
+
+    let mut visitor = Mentions { vars, found: false };
+    visitor.visit_expr(expr);
+    visitor.found
+}
+
+fn attr_named(attr: &Attribute, name: &str) -> bool {
+    attr.path().segments.last().is_some_and(|segment| segment.ident == name)
+}
+
+/// Attributes of expressions that can carry `#[unsafe_math]`
+fn expr_attrs(expr: &mut Expr) -> Option<&mut Vec<Attribute>> {
+    Some(match expr {
+        Expr::Array(e) => &mut e.attrs,
+        Expr::Assign(e) => &mut e.attrs,
+        Expr::Binary(e) => &mut e.attrs,
+        Expr::Block(e) => &mut e.attrs,
+        Expr::Call(e) => &mut e.attrs,
+        Expr::Cast(e) => &mut e.attrs,
+        Expr::Closure(e) => &mut e.attrs,
+        Expr::ForLoop(e) => &mut e.attrs,
+        Expr::If(e) => &mut e.attrs,
+        Expr::Index(e) => &mut e.attrs,
+        Expr::Loop(e) => &mut e.attrs,
+        Expr::Match(e) => &mut e.attrs,
+        Expr::MethodCall(e) => &mut e.attrs,
+        Expr::Paren(e) => &mut e.attrs,
+        Expr::Return(e) => &mut e.attrs,
+        Expr::Tuple(e) => &mut e.attrs,
+        Expr::Unary(e) => &mut e.attrs,
+        Expr::Unsafe(e) => &mut e.attrs,
+        Expr::While(e) => &mut e.attrs,
+        _ => return None,
+    })
+}
 
 impl VisitMut for UnsafeMathVisitor {
+    fn visit_macro_mut(&mut self, mac: &mut Macro) {
+        let Some(name) = mac.path.segments.last().map(|segment| &segment.ident) else {
+            return;
+        };
+        // bodies of macro definitions are not expressions, even if they happen to parse as ones
+        let safe = name == "safe_math";
+        if name == "macro_rules" || !(safe || self.config.macros.allows(name)) {
+            return;
+        }
+        if let Some(mut args) = MacroArgs::parse(mac.tokens.clone()) {
+            // std reports the condition of `assert!(cond)` as text, which must not be the generated code
+            let condition = (name == "assert" || name == "debug_assert").then(|| args.condition().cloned()).flatten();
+            // `safe_math!` itself expands to its argument, only nested `#[unsafe_math]` is rewritten inside
+            self.enter(safe.then_some(Scope::Safe), |visitor| args.visit_with(visitor));
+            if let Some(condition) = condition {
+                args.add_message(&condition);
+            }
+            mac.tokens = args.to_token_stream();
+        }
+    }
+
+    // patterns have expressions (`-1..=1`), but only constant ones
+    fn visit_pat_mut(&mut self, _pat: &mut syn::Pat) {}
+
+    fn visit_local_mut(&mut self, local: &mut syn::Local) {
+        let scope = self.nested_scope(&mut local.attrs, false);
+        self.enter(scope, |visitor| visit_mut::visit_local_mut(visitor, local));
+    }
+
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+        // `shallow` leaves fn items declared inside the scope alone
+        if !(self.config.shallow && matches!(stmt, Stmt::Item(Item::Fn(_)))) {
+            visit_mut::visit_stmt_mut(self, stmt);
+        }
+    }
+
+    fn visit_stmt_macro_mut(&mut self, stmt: &mut syn::StmtMacro) {
+        let scope = self.nested_scope(&mut stmt.attrs, false);
+        self.enter(scope, |visitor| visit_mut::visit_stmt_macro_mut(visitor, stmt));
+    }
+
     fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        let block = matches!(expr, Expr::Block(_));
+        if let Some(scope) = expr_attrs(expr).and_then(|attrs| self.nested_scope(attrs, block)) {
+            let assume = matches!(scope, Scope::Unsafe(_));
+            return self.enter(Some(scope), |visitor| {
+                visitor.visit_expr_mut(expr);
+                if let (true, Expr::Block(block)) = (assume, expr) {
+                    block.block.stmts.splice(0..0, visitor.assumptions());
+                }
+            });
+        }
+        if self.config.shallow && matches!(expr, Expr::Closure(_)) {
+            return;
+        }
+        if self.safe {
+            return visit_mut::visit_expr_mut(self, expr);
+        }
+        let access = std::mem::replace(&mut self.access, Access::Read);
+
         // strip any parentheses around the current expression
-        // (otherwise unneсessary parentheses may appear since we introduce function calls which already have parentheses)
-        while let Expr::Paren(expr_paren) = expr {
-            *expr = *expr_paren.expr.clone();
+        // (otherwise unneсessary parentheses may appear since we introduce function calls which already have parentheses).
+        // Invisible groups (`$e:expr` fragments of macro_rules) are treated the same way: compiler does not always respect them
+        // in proc macro output, so we rely on syn putting real parentheses back where precedence needs them
+        while let Expr::Paren(ExprParen { expr: inner, .. }) | Expr::Group(ExprGroup { expr: inner, .. }) = expr {
+            *expr = *inner.clone();
+        }
+
+        // remember how expression looked before rewriting, checked mode reports it on UB
+        let source = matches!(expr, Expr::Binary(_) | Expr::Unary(_) | Expr::MethodCall(_) | Expr::Index(_) | Expr::Cast(_))
+            .then(|| source_text(expr.to_token_stream()))
+            .filter(|_| matches!(expr, Expr::Index(_)) || self.selected(expr));
+
+        // compound assignment may need its operands as written, before children are rewritten, see rewrite_binary
+        let original = match &*expr {
+            Expr::Binary(binary) if source.is_some() && is_compound_assign(&binary.op) => Some(binary.clone()),
+            _ => None,
+        };
+
+        // visit children before. Places get to know how they are used, everything else is read
+        match expr {
+            Expr::Assign(assign) => {
+                self.visit_place_mut(&mut assign.left, Access::Write);
+                self.visit_expr_mut(&mut assign.right);
+            }
+            Expr::Binary(binary) if is_compound_assign(&binary.op) => {
+                self.visit_place_mut(&mut binary.left, Access::Write);
+                self.visit_expr_mut(&mut binary.right);
+            }
+            Expr::Reference(reference) if reference.mutability.is_some() => {
+                self.visit_place_mut(&mut reference.expr, Access::Write);
+            }
+            Expr::MethodCall(call) => {
+                // methods we rewrite take receiver by value, others may take `&mut self`
+                let access = match self.fast_method(call) {
+                    true => Access::Read,
+                    false => Access::Unknown,
+                };
+                self.visit_place_mut(&mut call.receiver, access);
+                call.args.iter_mut().for_each(|arg| self.visit_expr_mut(arg));
+            }
+            Expr::Index(index) => {
+                self.visit_place_mut(&mut index.expr, access);
+                self.in_context(Context::Index, |visitor| visitor.visit_expr_mut(&mut index.index));
+            }
+            Expr::Range(range) => self.in_context(Context::RangeBounds, |visitor| {
+                (range.start.iter_mut().chain(&mut range.end)).for_each(|bound| visitor.visit_expr_mut(bound));
+            }),
+            Expr::Field(field) => self.visit_place_mut(&mut field.base, access),
+            Expr::Unary(ExprUnary {
+                op: UnOp::Deref(_),
+                expr: operand,
+                ..
+            }) => self.visit_place_mut(operand, access),
+            _ => visit_mut::visit_expr_mut(self, expr),
+        }
+
+        // replace operations with fast ones
+        let rewritten = match (&*expr, source) {
+            (Expr::Binary(binary), Some(source)) => self.rewrite_binary(binary, original.as_ref(), &source),
+            (Expr::Unary(unary), Some(source)) => self.rewrite_unary(unary, &source),
+            (Expr::MethodCall(call), Some(source)) => self.rewrite_method(call, &source),
+            (Expr::Index(index), Some(source)) => self.rewrite_index(index, access, &source),
+            (Expr::Cast(cast), Some(source)) => self.rewrite_cast(cast, &source),
+            _ => None,
+        };
+        if let Some(rewritten) = rewritten {
+            *expr = rewritten;
+        }
+    }
+}
+
+impl UnsafeMathVisitor {
+    /// Method name for picking implementation (`add_kind`) and import of traits providing it,
+    /// operand types decide between fast traits and std::ops, see unsafe_math_trait::__private
+    fn kind(&self, name: &str) -> (Ident, proc_macro2::TokenStream) {
+        self.kind_with(name, &["Fast", "Ops"])
+    }
+
+    fn kind_with(&self, name: &str, levels: &[&str]) -> (Ident, proc_macro2::TokenStream) {
+        // mul_add => FastMulAddKind, OpsMulAddKind
+        let camel: String = name
+            .split('_')
+            .map(|word| word[..1].to_uppercase() + &word[1..])
+            .collect();
+        let traits = levels
+            .iter()
+            .map(|level| Ident::new(&format!("{level}{camel}Kind"), Span::call_site()));
+        let krate = &self.config.krate;
+        (
+            Ident::new(&format!("{name}_kind"), Span::call_site()),
+            quote! { use #krate::__private::{#(#traits as _),*}; },
+        )
+    }
+
+    /// Kind of binary operator, tries operands of the same type first (`SameAddKind`), for shifts a result of the left one's type
+    fn binary_kind(&self, op: Op) -> (Ident, proc_macro2::TokenStream) {
+        let name = op.name();
+        match self.config.policy {
+            Policy::Wrapping | Policy::Saturating => self.op_kind(name),
+            _ if self.config.types.is_some() => self.kind_with(&format!("only_{name}"), &["Same", "Fast", "Ops"]),
+            _ => self.kind_with(name, &["Same", "Fast", "Ops"]),
         }
+    }
 
-        // visit children before
-        visit_mut::visit_expr_mut(self, expr);
+    /// Kind of compound assignment, `add_assign` is fast only for places that can be copied and hold the result.
+    /// Policies use the same kinds as for binary operators, their operands are of the same type
+    fn assign_kind(&self, op: Op) -> (Ident, proc_macro2::TokenStream) {
+        match self.config.policy {
+            Policy::Wrapping | Policy::Saturating => self.op_kind(op.name()),
+            _ => self.op_kind(&format!("{}_assign", op.name())),
+        }
+    }
 
-        // replace binary operations with fast ones
-        if let Expr::Binary(syn::ExprBinary {
+    /// Kind of operator under current policy, `add` => `wrapping_add` for `policy = wrapping`,
+    /// `only_add` for `int`, `float` and `types(...)`. Also used for numeric methods
+    fn op_kind(&self, name: &str) -> (Ident, proc_macro2::TokenStream) {
+        match self.config.policy {
+            Policy::Wrapping | Policy::Saturating => self.kind(&format!("{}_{name}", self.config.policy.name())),
+            _ if self.config.types.is_some() => self.kind(&format!("only_{name}")),
+            _ => self.kind(name),
+        }
+    }
+
+    /// What fast operations do about UB and which float flags they use, see `unsafe_math_trait::__private::Mode`
+    fn mode(&self) -> proc_macro2::TokenStream {
+        let krate = &self.config.krate;
+        let mode = match self.config.policy {
+            Policy::Checked => quote! { #krate::__private::Mode::CHECKED },
+            Policy::Trap => quote! { #krate::__private::Mode::TRAP },
+            _ => quote! { #krate::__private::Mode::unchecked(::core::cfg!(debug_assertions)) },
+        };
+        let mode = match &self.config.float {
+            Some(flags) => quote! {
+                #mode.float(#krate::FloatFlags::NONE #(.union(#krate::FloatFlags::#flags))*)
+            },
+            None => mode,
+        };
+        match &self.config.types {
+            Some(types) => quote! {
+                #mode.only(#krate::__private::Types::NONE #(.union(#krate::__private::Types::#types))*)
+            },
+            None => mode,
+        }
+    }
+
+    /// Expression as written, for code that is only type checked. Nested scopes are taken off like in `safe_math!`,
+    /// rewriting it once more would repeat everything nested in it
+    fn as_written(&self, expr: &Expr) -> Expr {
+        let mut visitor = UnsafeMathVisitor::new(self.config.clone());
+        visitor.safe = true;
+        let mut expr = expr.clone();
+        visitor.visit_expr_mut(&mut expr);
+        expr
+    }
+
+    fn rewrite_binary(&self, binary: &ExprBinary, original: Option<&ExprBinary>, source: &str) -> Option<Expr> {
+        let ExprBinary {
             left, op, right, ..
-        }) = expr
-            && let Some(method) = binary_op_to_method_name(op)
-        {
-            let rewritten = quote! { UnsafeMath::#method(#left, #right) };
-
-            *expr = match op {
-                // for compound assigns, we assign the result back to the left expression
-                BinOp::AddAssign(_)
-                | BinOp::SubAssign(_)
-                | BinOp::MulAssign(_)
-                | BinOp::DivAssign(_)
-                | BinOp::RemAssign(_)
-                | BinOp::ShlAssign(_)
-                | BinOp::ShrAssign(_)
-                | BinOp::BitAndAssign(_)
-                | BinOp::BitOrAssign(_)
-                | BinOp::BitXorAssign(_) => {
-                    syn::parse_quote! { #left = #rewritten }
-                }
-                // for regular binary ops, we just replace the expression
-                _ => {
-                    syn::parse_quote! { #rewritten }
+        } = binary;
+        let op_kind = Op::from_bin_op(op).filter(|&op| self.config.ops.contains(op))?;
+
+        // spanned, so that #[track_caller] in checked mode points at the original expression
+        let span = binary.span();
+        let name = op_kind.name();
+        let func = Ident::new(name, span);
+        let func_assign = Ident::new(&format!("{name}_assign"), span);
+        let (kind, kinds) = match is_compound_assign(op) {
+            true => self.assign_kind(op_kind),
+            false => self.binary_kind(op_kind),
+        };
+        let krate = &self.config.krate;
+        let mode = self.mode();
+        // mixed_site, so these can't collide with user variables
+        let lhs = Ident::new("lhs", Span::mixed_site());
+        let rhs = Ident::new("rhs", Span::mixed_site());
+        let place = Ident::new("place", Span::mixed_site());
+
+        Some(if is_compound_assign(op) && (is_fixed_place(left) || is_pure_rhs(right, left)) {
+            // evaluating a variable or its fields has no side effects and can't be interleaved with the other side,
+            // so evaluation order doesn't matter and operands are probed after evaluating them, like binary operators
+            syn::parse_quote_spanned! {span=>
+                match (#right, &mut #left) {
+                    (#rhs, #place) => {
+                        #kinds
+                        (&#krate::__private::Probe::new(&*#place, &#rhs))
+                            .#kind()
+                            .#func_assign(#place, #rhs, #mode, #source)
+                    }
                 }
+            }
+        } else if is_compound_assign(op) {
+            // otherwise the order depends on operand types: builtin `a[i] += f()` evaluates `f()` first,
+            // overloaded one evaluates `a[i]` first. So types are probed without evaluating anything,
+            // and each order has its own branch. Tag does either fast or plain operation, so the branches don't repeat operands for it.
+            // Place is borrowed once, so side effects in it (`arr[next()] += 1`) happen once.
+            // Probe gets the operands as written: the rewritten ones already repeat whatever compound assignment is nested in them
+            let probe = Ident::new("probe", Span::mixed_site());
+            let tag = Ident::new("tag", Span::mixed_site());
+            let order = Ident::new("order", Span::mixed_site());
+            // built as syntax trees, so that printing puts parentheses around operands if needed
+            let borrow = |operand: &Expr| {
+                let mut borrowed: ExprReference = syn::parse_quote!(&operand);
+                borrowed.expr = Box::new(operand.clone());
+                borrowed
             };
+            let original = original.unwrap_or(binary);
+            let (left_ref, right_ref) = (borrow(&self.as_written(&original.left)), borrow(&self.as_written(&original.right)));
+            let mut operands: ExprMethodCall = syn::parse_quote!(place.unsafe_math_operands(#right));
+            operands.receiver = left.clone();
+            // not spanned, like the kinds, these imports are unused for one of the orders
+            let order_kinds = quote! { use #krate::__private::{PlaceFirstKind as _, RhsFirstKind as _}; };
+            let operands_trait = quote! { use #krate::__private::AssignOperands as _; };
+            syn::parse_quote_spanned! {span=> {
+                let #probe = #krate::__private::Probe::unknown();
+                // diverges before the operands, so they are only type checked here (moving a `String` in them is fine)
+                #[allow(unreachable_code)]
+                let () = if false {
+                    #krate::__private::unreachable();
+                    #probe.infer(#left_ref, #right_ref);
+                };
+                let (#tag, #order) = {
+                    #kinds
+                    #order_kinds
+                    ((&#probe).#kind(), (&#probe).assign_order())
+                };
+                // the branch of the other order is never taken, and is cut off by `enter_*()` for borrow checker.
+                // Operands are matched on to keep their temporaries (`*cell.borrow_mut() += 1`) until the end
+                #[allow(unreachable_code, clippy::blocks_in_conditions)]
+                let () = if #order.rhs_first() {
+                    (#order.enter_rhs_first())();
+                    match #right {
+                        #rhs => #tag.#func_assign(&mut #left, #rhs, #mode, #source),
+                    }
+                } else {
+                    (#order.enter_place_first())();
+                    #operands_trait
+                    match #operands {
+                        (#place, #rhs) => #tag.#func_assign(#place, #rhs, #mode, #source),
+                    }
+                };
+            }}
+        } else {
+            // for regular binary ops, we just replace the expression.
+            // Operands are bound by match first, so they are not evaluated inside the block with imports
+            syn::parse_quote_spanned! {span=>
+                match (#left, #right) {
+                    (#lhs, #rhs) => {
+                        #kinds
+                        (&#krate::__private::Probe::new(&#lhs, &#rhs))
+                            .#kind()
+                            .#func(#lhs, #rhs, #mode, #source)
+                    }
+                }
+            }
+        })
+    }
+
+    fn rewrite_unary(&self, unary: &ExprUnary, source: &str) -> Option<Expr> {
+        let ExprUnary { op, expr, .. } = unary;
+        // negative literals are constants anyway, and `-2147483648` must stay a literal to fit i32
+        if !matches!(op, UnOp::Neg(_)) || matches!(**expr, Expr::Lit(_)) || !self.config.ops.contains(Op::Neg) {
+            return None;
+        }
+
+        let (kind, kinds) = self.op_kind(Op::Neg.name());
+        let krate = &self.config.krate;
+        let mode = self.mode();
+        let operand = Ident::new("operand", Span::mixed_site());
+        let func = Ident::new("neg", unary.span());
+        Some(syn::parse_quote_spanned! {unary.span()=>
+            match #expr {
+                #operand => {
+                    #kinds
+                    (&#krate::__private::Probe::new(&#operand, &()))
+                        .#kind()
+                        .#func(#operand, #mode, #source)
+                }
+            }
+        })
+    }
+
+    /// Whether method call is one of FAST_METHODS and will be rewritten.
+    /// Wrapping and saturating policies leave numeric methods as they are
+    fn fast_method(&self, call: &ExprMethodCall) -> bool {
+        let numeric = self.config.ops.contains(Op::Methods)
+            && self.config.policy.is_fast()
+            && call.turbofish.is_none()
+            && FAST_METHODS
+                .iter()
+                .any(|(name, arity)| call.method == name && call.args.len() == *arity);
+        numeric || (self.config.cast && vek_cast_target(call).is_some())
+    }
+
+    fn rewrite_method(&self, call: &ExprMethodCall, source: &str) -> Option<Expr> {
+        if !self.fast_method(call) {
+            return None;
+        }
+        let ExprMethodCall {
+            receiver,
+            method,
+            args,
+            ..
+        } = call;
+
+        // receiver is evaluated once and must not be moved when the original method borrows it,
+        // so only values are bound by value. Simple places are just repeated, other places are borrowed
+        // (moving out of them is an error anyway)
+        let recv = Ident::new("recv", Span::mixed_site());
+        let tag = Ident::new("tag", Span::mixed_site());
+        let mut bound = Vec::new();
+        let mut patterns = Vec::new();
+        let receiver = match Receiver::of(receiver) {
+            Receiver::Pure => receiver.to_token_stream(),
+            Receiver::Place => {
+                bound.push(quote! { &#receiver });
+                patterns.push(recv.clone());
+                quote! { (*#recv) }
+            }
+            Receiver::Value => {
+                bound.push(receiver.to_token_stream());
+                patterns.push(recv.clone());
+                recv.to_token_stream()
+            }
+        };
+        // arguments go after receiver, same as in the original call
+        let arg_names: Vec<_> = (0..args.len())
+            .map(|i| Ident::new(&format!("arg{i}"), Span::mixed_site()))
+            .collect();
+        bound.extend(args.iter().map(ToTokens::to_token_stream));
+        patterns.extend(arg_names.iter().cloned());
+
+        // vek's `v.as_::<D>()` is probed with D, numeric methods only need receiver type
+        let (kind, kinds, target, generics) = match vek_cast_target(call) {
+            Some(target) => {
+                let (kind, kinds) = self.kind("as");
+                (kind, kinds, quote! { #target }, quote! { ::<#target, _> })
+            }
+            None => {
+                let (kind, kinds) = self.op_kind(&method.to_string());
+                (kind, kinds, quote! { () }, quote! {})
+            }
+        };
+        let turbofish = &call.turbofish;
+        let krate = &self.config.krate;
+        let mode = self.mode();
+        let dispatch = quote_spanned! {call.span()=> {
+            #kinds
+            let #tag = (&#krate::__private::Probe::<_, #target>::of(&#receiver)).#kind();
+            if #tag.is_fast(#mode) {
+                #tag.#method #generics(&#receiver, (#(#arg_names,)*), #mode, #source)
+            } else {
+                #receiver.#method #turbofish(#(#arg_names),*)
+            }
+        }};
+        Some(if bound.is_empty() {
+            syn::parse2(dispatch).ok()?
+        } else {
+            syn::parse_quote_spanned! {call.span()=>
+                match (#(#bound,)*) {
+                    (#(#patterns,)*) => #dispatch
+                }
+            }
+        })
+    }
+
+    fn rewrite_cast(&self, cast: &ExprCast, source: &str) -> Option<Expr> {
+        let ExprCast { expr, ty, .. } = cast;
+        // literals are constants anyway, and `_` target can't be probed
+        if !self.config.cast || matches!(**expr, Expr::Lit(_)) || matches!(**ty, Type::Infer(_)) {
+            return None;
+        }
+
+        let (kind, kinds) = self.kind("cast");
+        let krate = &self.config.krate;
+        let mode = self.mode();
+        let value = Ident::new("value", Span::mixed_site());
+        let tag = Ident::new("tag", Span::mixed_site());
+        let func = Ident::new("cast", cast.span());
+        // casts that are not numeric (pointers, enums, vek via `as_`) keep `as` through the fallback branch
+        Some(syn::parse_quote_spanned! {cast.span()=>
+            match #expr {
+                #value => {
+                    #kinds
+                    let #tag = (&#krate::__private::Probe::<_, #ty>::of(&#value)).#kind();
+                    if #tag.is_fast(#mode) {
+                        #tag.#func(#value, #mode, #source)
+                    } else {
+                        #value as #ty
+                    }
+                }
+            }
+        })
+    }
+
+    fn rewrite_index(&self, index: &ExprIndex, access: Access, source: &str) -> Option<Expr> {
+        if !self.config.index {
+            return None;
         }
+        let ExprIndex {
+            expr: base,
+            index: index_expr,
+            ..
+        } = index;
+        let krate = &self.config.krate;
+        let (borrowed, kind_name, func, borrow) = match access {
+            Access::Read => (quote! { &#base }, "index", Ident::new("index", index.span()), quote! { & }),
+            // method call autoref, `&mut v` would require `v` itself to be mutable even when it is `&mut Vec<T>`.
+            // The block is for the import, its temporaries are gone after it, so temporary bases (`v.as_mut_slice()[i] = x`)
+            // are left as they are
+            Access::Write if !matches!(Receiver::of(base), Receiver::Value) => {
+                // built as syntax tree, so that printing puts parentheses around `*base` if needed
+                let mut borrowed: ExprMethodCall = syn::parse_quote!(base.place_mut());
+                borrowed.receiver = base.clone();
+                let borrowed = quote! { { use #krate::__private::PlaceMut as _; #borrowed } };
+                (borrowed, "index_mut", Ident::new("index_mut", index.span()), quote! { &mut })
+            }
+            Access::Write | Access::Unknown => return None,
+        };
+
+        let (kind, kinds) = self.kind(kind_name);
+        let mode = self.mode();
+        let base_ident = Ident::new("base", Span::mixed_site());
+        let index_ident = Ident::new("index", Span::mixed_site());
+        let tag = Ident::new("tag", Span::mixed_site());
+        // result is a place again (deref of returned reference), so it can be assigned to or borrowed.
+        // Temporaries of the base (`s.as_bytes()[i]`) live in match scrutinee, as long as they do without the macro.
+        // Non-slice containers (HashMap...) keep their Index impls through the fallback branch
+        Some(syn::parse_quote_spanned! {index.span()=>
+            *match (#borrowed, #index_expr) {
+                (#base_ident, #index_ident) => {
+                    #kinds
+                    let #tag = (&#krate::__private::Probe::new(&*#base_ident, &#index_ident)).#kind();
+                    if #tag.is_fast(#mode) {
+                        #tag.#func(#base_ident, #index_ident, #mode, #source)
+                    } else {
+                        #borrow #base_ident[#index_ident]
+                    }
+                }
+            }
+        })
     }
 }
 
-/// Returns name of corresponding function in UnsafeMath trait, if any.
-fn binary_op_to_method_name(op: &BinOp) -> Option<Ident> {
-    let name = match op {
-        BinOp::Add(_) | BinOp::AddAssign(_) => "fast_add",
-        BinOp::Sub(_) | BinOp::SubAssign(_) => "fast_sub",
-        BinOp::Mul(_) | BinOp::MulAssign(_) => "fast_mul",
-        BinOp::Div(_) | BinOp::DivAssign(_) => "fast_div",
-        BinOp::Rem(_) | BinOp::RemAssign(_) => "fast_rem",
-        BinOp::Shl(_) | BinOp::ShlAssign(_) => "fast_shl",
-        BinOp::Shr(_) | BinOp::ShrAssign(_) => "fast_shr",
-        _ => return None,
-    };
-    Some(Ident::new(name, Span::call_site()))
+/// Place that is evaluated without side effects, a variable, its fields or what it points to: `self.pos.x`, `*out`
+fn is_fixed_place(expr: &Expr) -> bool {
+    match expr {
+        Expr::Path(path) => path.qself.is_none(),
+        Expr::Field(field) => is_fixed_place(&field.base),
+        Expr::Paren(paren) => is_fixed_place(&paren.expr),
+        Expr::Unary(ExprUnary {
+            op: UnOp::Deref(_),
+            expr,
+            ..
+        }) => matches!(&**expr, Expr::Path(path) if path.qself.is_none()),
+        _ => false,
+    }
+}
+
+/// Right side of compound assignment that can be evaluated before the place without changing anything:
+/// a literal, or a variable (or its field) the place doesn't mention, `v[i] += s` must not move `s` before `v[s.len()]`
+fn is_pure_rhs(rhs: &Expr, place: &Expr) -> bool {
+    match rhs {
+        Expr::Lit(_) => true,
+        Expr::Path(path) if path.qself.is_none() => match path.path.get_ident() {
+            Some(ident) => !mentions(place, std::slice::from_ref(ident)),
+            None => true,
+        },
+        Expr::Field(field) => is_pure_rhs(&field.base, place),
+        Expr::Paren(paren) => is_pure_rhs(&paren.expr, place),
+        _ => false,
+    }
+}
+
+fn is_compound_assign(op: &BinOp) -> bool {
+    matches!(
+        op,
+        BinOp::AddAssign(_)
+            | BinOp::SubAssign(_)
+            | BinOp::MulAssign(_)
+            | BinOp::DivAssign(_)
+            | BinOp::RemAssign(_)
+            | BinOp::ShlAssign(_)
+            | BinOp::ShrAssign(_)
+            | BinOp::BitAndAssign(_)
+            | BinOp::BitOrAssign(_)
+            | BinOp::BitXorAssign(_)
+    )
+}
+
+/// `D` of `v.as_::<D>()`, vek's element-wise cast
+fn vek_cast_target(call: &ExprMethodCall) -> Option<&Type> {
+    let turbofish = call.turbofish.as_ref()?;
+    match (turbofish.args.len(), turbofish.args.first()) {
+        (1, Some(GenericArgument::Type(target))) if call.method == "as_" && call.args.is_empty() => Some(target),
+        _ => None,
+    }
+}
+
+/// Methods rewritten to fast versions, with number of arguments
+const FAST_METHODS: [(&str, usize); 7] = [
+    ("abs", 0),
+    ("div_euclid", 1),
+    ("rem_euclid", 1),
+    ("pow", 1),
+    ("sqrt", 0),
+    ("powi", 1),
+    ("mul_add", 2),
+];
+
+/// How method receiver can be evaluated once
+enum Receiver {
+    /// Place without side effects, like `self.pos.x` or `v[i]`, safe to repeat
+    Pure,
+    /// Other places, `v[next()]` or `*ptr`
+    Place,
+    /// Temporary value
+    Value,
+}
+
+impl Receiver {
+    fn of(expr: &Expr) -> Receiver {
+        match expr {
+            Expr::Path(path) if path.qself.is_none() => Receiver::Pure,
+            Expr::Field(field) => Receiver::of(&field.base),
+            Expr::Index(index) => match (Receiver::of(&index.expr), &*index.index) {
+                (Receiver::Pure, Expr::Lit(_) | Expr::Path(_)) => Receiver::Pure,
+                _ => Receiver::Place,
+            },
+            Expr::Unary(ExprUnary {
+                op: UnOp::Deref(_), ..
+            }) => Receiver::Place,
+            _ => Receiver::Value,
+        }
+    }
 }
 
 struct StmtWithComma(Stmt);
@@ -82,22 +799,144 @@ impl Parse for StmtWithComma {
     }
 }
 
+/// Statements of `unsafe_math_block!`, optionally prefixed with `#![unsafe_math(...)]` config
+struct ConfiguredBlock {
+    config: Config,
+    stmts: Vec<Stmt>,
+}
+impl Parse for ConfiguredBlock {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut builder = ConfigBuilder::default();
+        for attr in Attribute::parse_inner(input)? {
+            if !attr.path().is_ident("unsafe_math") {
+                return Err(syn::Error::new_spanned(
+                    attr,
+                    "expected `#![unsafe_math(...)]` config",
+                ));
+            }
+            attr.parse_nested_meta(|meta| builder.parse_meta(meta))?;
+        }
+        let stmts = syn::Block::parse_within(input)?;
+        Ok(ConfiguredBlock {
+            config: builder.build(),
+            stmts,
+        })
+    }
+}
+
 /// Main `unsafe_math` macro. Replaces all binary operations with their unchecked/f_fast versions.
+///
+/// Operators to rewrite can be chosen with arguments:
+/// `#[unsafe_math(add, sub, mul)]` rewrites only listed ones, `#[unsafe_math(not(div, rem))]` rewrites everything except listed.
+/// Known operators are `add`, `sub`, `mul`, `div`, `rem`, `shl`, `shr`, `neg` (unary minus)
+/// and `methods` (numeric method calls: `abs`, `pow`, `powi`, `sqrt`, `mul_add`, `div_euclid`, `rem_euclid`).
+///
+/// `index` additionally removes bounds checks from indexing of slices, arrays, `Vec`, `str` and `String`.
+/// `cast` makes `as` casts from float to int unchecked, and lets narrowing integer casts assume the value fits.
+///
+/// Float operations get LLVM fast-math flags that don't assume anything (`reassoc`, `contract`, `nsz`, `arcp`, `afn`):
+/// results may change in last bits, but NaN and infinity are handled as usual.
+/// `float(reassoc, contract, nnan, ninf, nsz, arcp, afn)` picks flags explicitly, `float(algebraic)` is the value-changing ones,
+/// `float(all)` is all of them. `reassoc` or `contract` use the algebraic intrinsics, which allow all value-changing flags,
+/// `nsz`, `arcp` and `afn` alone keep operations exact: Rust has no intrinsics for them.
+/// NaN and infinity are UB only with `nnan` and `ninf`, see `FloatFlags`.
+///
+/// `int` and `float` limit rewriting of operators and numeric methods to integers or floats, `types(f32, u32)` to listed types.
+/// Other types keep their plain operators. Vek and derived types are rewritten when all their scalars are included.
+/// Bare `float` is the type filter, `float(...)` sets the flags.
+///
+/// `vars(i, stride)` rewrites only arithmetic (operators, numeric methods, casts) that mentions listed variables
+/// anywhere in its operands, `only(index, range_bounds)` only arithmetic inside subscripts and range bounds:
+/// `data[i * stride + j]` and `0..n - 1`. Indexing itself is still controlled by `index`.
+/// `shallow` leaves closures and fn items declared inside as they are.
+///
+/// `assume(n < 4096, a.len() == b.len())` tells the optimizer about value ranges: conditions are checked at the top
+/// of the fn or block with `core::hint::assert_unchecked`, a false one is UB. When checks are on (debug builds, `policy = checked`),
+/// they are asserted instead, panic message has the condition. Conditions are evaluated as written, keep them cheap.
+///
+/// `policy = ...` picks what rewritten operators do:
+/// - `unchecked` (default): fast operations, checked only in debug builds
+/// - `checked`: fast operations, UB panics in every build
+/// - `trap`: fast operations, UB aborts the process in every build, without formatting a panic message
+/// - `wrapping`, `saturating`: integer operators of `WrappingMath`/`SaturatingMath`, for both operands of the same type.
+///   Other types (floats, vek with scalar) keep their plain operators, numeric methods are not rewritten.
+///   `index` and `cast` stay unchecked when enabled
+///
+/// Arguments of macro invocations (`vec!`, `assert_eq!`, `format!`, your `macro_rules!`...) are rewritten too,
+/// when they parse as comma separated expressions. `macros(vec, assert_eq)` limits that to listed macros,
+/// `macros(not(my_dsl))` excludes listed ones. `stringify!` and `concat!` are left alone unless listed,
+/// `matches!` and `assert_matches!` always are, since they take patterns.
+///
+/// `#[safe_math]` on a statement or `safe_math!(expr)` keeps operators inside as they are,
+/// a nested `#[unsafe_math]` rewrites them again.
+///
+/// Generated code refers to `::unsafe_math`. When it is re-exported under another name, or used from `macro_rules!`,
+/// pass the path explicitly: `#[unsafe_math(crate = my_facade::unsafe_math)]` / `#[unsafe_math(crate = $crate)]`.
 #[proc_macro_attribute]
-pub fn unsafe_math(_args: TokenStream, item: TokenStream) -> TokenStream {
+pub fn unsafe_math(args: TokenStream, item: TokenStream) -> TokenStream {
+    let mut builder = ConfigBuilder::default();
+    let args_parser = syn::meta::parser(|meta| builder.parse_meta(meta));
+    parse_macro_input!(args with args_parser);
     let StmtWithComma(mut stmt) = parse_macro_input!(item as StmtWithComma);
-    let mut visitor = UnsafeMathVisitor;
-    visitor.visit_stmt_mut(&mut stmt);
+    let mut visitor = UnsafeMathVisitor::new(builder.build());
+    // not through the visitor, the fn item under the attribute is visited even with `shallow`
+    visit_mut::visit_stmt_mut(&mut visitor, &mut stmt);
+    if let Some(condition) = visitor.config.assume.first() {
+        let block = match &mut stmt {
+            Stmt::Item(Item::Fn(item)) => &mut item.block,
+            Stmt::Expr(Expr::Block(block), _) => &mut block.block,
+            _ => {
+                return syn::Error::new_spanned(condition, "`assume(...)` needs a fn or a block")
+                    .into_compile_error()
+                    .into();
+            }
+        };
+        block.stmts.splice(0..0, visitor.assumptions());
+    }
     TokenStream::from(quote! { #stmt })
 }
 
 /// Version of `unsafe_math` macro that wraps statements. Replaces all binary operations with their unchecked/f_fast versions.
+///
+/// Accepts the same arguments as `#[unsafe_math]` through leading inner attribute:
+/// `unsafe_math_block! { #![unsafe_math(add, mul)] ... }`
 #[proc_macro]
 pub fn unsafe_math_block(input: TokenStream) -> TokenStream {
-    let mut stmts: Vec<Stmt> = syn::parse_macro_input!(input with syn::Block::parse_within);
-    let mut visitor = UnsafeMathVisitor;
+    let ConfiguredBlock { config, mut stmts } = parse_macro_input!(input as ConfiguredBlock);
+    let mut visitor = UnsafeMathVisitor::new(config);
     for stmt in &mut stmts {
         visitor.visit_stmt_mut(stmt);
     }
+    stmts.splice(0..0, visitor.assumptions());
     TokenStream::from(quote!({ #(#stmts)* }))
 }
+
+/// Opts an expression out of the enclosing `#[unsafe_math]` scope: `safe_math!(len * size)` overflows the usual way
+/// (panics in debug builds). The scope leaves it as is, apart from nested `#[unsafe_math]`. Expands to its argument.
+///
+/// For statements there is `#[safe_math]`, it is recognized only inside the scope
+#[proc_macro]
+pub fn safe_math(input: TokenStream) -> TokenStream {
+    input
+}
+
+/// Implements fast operator traits (`FastAdd`, `FastShl`...) for a struct, so it gets fast operators under `#[unsafe_math]`
+/// and can be a field of other such structs. Operators themselves still come from the type's `std::ops` impls,
+/// the macro only rewrites operators that would compile without it.
+///
+/// By default every operation is applied field-wise, like for vek vectors. Works for named and tuple structs.
+/// Arithmetic operators are derived, shifts too when all fields are integers: struct with `f32` field gets `+` but no `<<`.
+/// Generic structs get the bounds on field types they need.
+///
+/// `#[unsafe_math(via_ops)]` forwards to the type's own `std::ops` impls instead (shifts through `Shl<u32>`/`Shr<u32>`),
+/// for types whose operators are not field-wise, e.g. matrices. Arithmetic operators are derived by default.
+///
+/// `#[unsafe_math(ops(mul, shl))]` derives only listed operators, with either strategy. Fields or the type must have them.
+/// `#[unsafe_math(crate = path)]` is accepted as for the attribute.
+#[proc_macro_derive(UnsafeMath, attributes(unsafe_math))]
+pub fn derive_unsafe_math(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as syn::DeriveInput);
+    derive::derive_unsafe_math(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
@@ -0,0 +1,80 @@
+//! Arguments of macro invocations
+//!
+//! syn keeps macro arguments as raw tokens, so visitor would never see `a * b` in `vec![a * b; n]` or `assert_eq!(x + y, z)`.
+//! Arguments are parsed as comma separated expressions (or `vec!`-like `elem; len`), visited and printed back.
+//! Anything that doesn't parse this way is left untouched, as are macros that use the text of their arguments
+//! (`stringify!`) or take patterns (`matches!`), see `MacroFilter`.
+
+use proc_macro2::TokenStream;
+use quote::ToTokens;
+use syn::{
+    parse::{ParseStream, Parser},
+    punctuated::Punctuated,
+    visit_mut::VisitMut,
+    Expr, Token,
+};
+
+pub enum MacroArgs {
+    List(Punctuated<Expr, Token![,]>),
+    Repeat {
+        elem: Box<Expr>,
+        semi: Token![;],
+        len: Box<Expr>,
+    },
+}
+
+impl MacroArgs {
+    pub fn parse(tokens: TokenStream) -> Option<Self> {
+        if let Ok(list) = Punctuated::parse_terminated.parse2(tokens.clone()) {
+            return Some(MacroArgs::List(list));
+        }
+        let repeat = |input: ParseStream| {
+            Ok(MacroArgs::Repeat {
+                elem: input.parse()?,
+                semi: input.parse()?,
+                len: input.parse()?,
+            })
+        };
+        repeat.parse2(tokens).ok()
+    }
+
+    /// Condition of `assert!(cond)` without a message, which std reports as text
+    pub fn condition(&self) -> Option<&Expr> {
+        match self {
+            MacroArgs::List(list) if list.len() == 1 => list.first(),
+            _ => None,
+        }
+    }
+
+    /// `assert!(cond)` => `assert!(cond, "assertion failed: {}", stringify!(original))`,
+    /// so that the message has the original condition rather than generated code
+    pub fn add_message(&mut self, original: &Expr) {
+        if let MacroArgs::List(list) = self {
+            list.push(syn::parse_quote!("assertion failed: {}"));
+            list.push(syn::parse_quote!(::core::stringify!(#original)));
+        }
+    }
+
+    pub fn visit_with(&mut self, visitor: &mut impl VisitMut) {
+        match self {
+            MacroArgs::List(list) => list.iter_mut().for_each(|expr| visitor.visit_expr_mut(expr)),
+            MacroArgs::Repeat { elem, len, .. } => {
+                visitor.visit_expr_mut(elem);
+                visitor.visit_expr_mut(len);
+            }
+        }
+    }
+}
+
+impl ToTokens for MacroArgs {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        match self {
+            MacroArgs::List(list) => list.to_tokens(tokens),
+            MacroArgs::Repeat { elem, semi, len } => {
+                elem.to_tokens(tokens);
+                semi.to_tokens(tokens);
+                len.to_tokens(tokens);
+            }
+        }
+    }
+}
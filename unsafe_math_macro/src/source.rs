@@ -0,0 +1,97 @@
+//! Text of expressions for checked mode messages
+//!
+//! `TokenStream::to_string` puts spaces between almost all tokens (`data [i]`, `v.as_ :: < i16 > ()`, `- a`),
+//! so tokens are printed here, roughly the way rustfmt would.
+//! Invisible groups (macro_rules fragments) are shown as parentheses, since that's how they behave
+
+use proc_macro2::{Delimiter, Spacing, TokenStream, TokenTree};
+
+pub fn source_text(tokens: TokenStream) -> String {
+    let mut text = String::new();
+    print(tokens, &mut text);
+    text
+}
+
+/// Keywords that are followed by a space even before parentheses, `if (a)`, `x as (u8)`
+const KEYWORDS: [&str; 13] = [
+    "as", "else", "for", "if", "in", "let", "loop", "match", "move", "mut", "return", "unsafe", "while",
+];
+
+fn print(tokens: TokenStream, out: &mut String) {
+    let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+    // no space before the first token, or after `.`, `::`, `(`...
+    let mut glue = true;
+    // depth of generic arguments opened with `::<`, or qualified paths `<T as Trait>::`
+    let mut angles = 0usize;
+    for (i, tree) in tokens.iter().enumerate() {
+        let prev = i.checked_sub(1).map(|i| &tokens[i]);
+        let next = tokens.get(i + 1);
+        let path_sep = |tree: Option<&TokenTree>| matches!(tree, Some(TokenTree::Punct(p)) if p.as_char() == ':');
+
+        let space = !glue
+            && match tree {
+                TokenTree::Punct(punct) => match punct.as_char() {
+                    '.' | ',' | ';' | '?' => false,
+                    ':' => !(path_sep(prev) || (punct.spacing() == Spacing::Joint && path_sep(next))),
+                    '>' => angles == 0,
+                    // macro call, `vec![...]`
+                    '!' => !matches!((prev, next), (Some(TokenTree::Ident(_)), Some(TokenTree::Group(_)))),
+                    _ => true,
+                },
+                // calls and indexing
+                TokenTree::Group(group) if group.delimiter() != Delimiter::Brace => match prev {
+                    Some(TokenTree::Ident(ident)) => KEYWORDS.contains(&ident.to_string().as_str()),
+                    Some(TokenTree::Group(_) | TokenTree::Literal(_)) => false,
+                    Some(TokenTree::Punct(punct)) => !(punct.as_char() == '>' && angles == 0 && !out.ends_with(" >")),
+                    None => true,
+                },
+                _ => true,
+            };
+        if space {
+            out.push(' ');
+        }
+
+        glue = match tree {
+            TokenTree::Group(group) => {
+                let (open, close) = match group.delimiter() {
+                    Delimiter::Parenthesis | Delimiter::None => ("(", ")"),
+                    Delimiter::Bracket => ("[", "]"),
+                    Delimiter::Brace => ("{ ", " }"),
+                };
+                out.push_str(open);
+                print(group.stream(), out);
+                out.push_str(close);
+                false
+            }
+            TokenTree::Punct(punct) => {
+                out.push(punct.as_char());
+                // operand position, where `-`, `&`, `<T as Trait>` are prefixes
+                let operand = match prev {
+                    None => true,
+                    Some(TokenTree::Punct(prev)) => prev.spacing() == Spacing::Alone && !matches!(prev.as_char(), '>' | '?'),
+                    Some(TokenTree::Ident(ident)) => KEYWORDS.contains(&ident.to_string().as_str()),
+                    _ => false,
+                };
+                match punct.as_char() {
+                    '<' if path_sep(prev) || operand => {
+                        angles += 1;
+                        true
+                    }
+                    '>' if angles > 0 => {
+                        angles -= 1;
+                        // `::<T>::new` and `::<T>()` continue the path
+                        false
+                    }
+                    '.' | ':' | '\'' | '#' => true,
+                    '!' if matches!((prev, next), (Some(TokenTree::Ident(_)), Some(TokenTree::Group(_)))) => true,
+                    '-' | '!' | '*' | '&' => operand || punct.spacing() == Spacing::Joint,
+                    _ => punct.spacing() == Spacing::Joint,
+                }
+            }
+            tree => {
+                out.push_str(&tree.to_string());
+                false
+            }
+        };
+    }
+}
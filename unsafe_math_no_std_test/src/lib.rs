@@ -0,0 +1,119 @@
+//! Code generated by `unsafe_math` must only refer to `core`: this crate is `no_std`,
+//! so any `::std` path in the expansion fails to compile here.
+//!
+//! Build the whole crate graph without std with
+//! `cargo build -p unsafe_math_no_std_test --target <some no_std target>`
+
+#![no_std]
+
+use unsafe_math::{unsafe_math, unsafe_math_block, UnsafeMath};
+
+#[unsafe_math]
+pub fn dot(a: &[i32; 4], b: &[i32; 4]) -> i32 {
+    let mut sum = 0;
+    for i in 0..4 {
+        sum += a[i] * b[i];
+    }
+    sum
+}
+
+#[unsafe_math(index)]
+pub fn sum_slice(data: &[u32], window: usize) -> u32 {
+    let mut sum = 0;
+    for i in 0..window {
+        sum += data[i] << 1 >> 1;
+    }
+    sum
+}
+
+#[unsafe_math]
+pub fn methods(a: i64, b: i64) -> (i64, i64, i64, u8) {
+    (a.pow(2), (-a).abs(), a.rem_euclid(b), a as u8)
+}
+
+#[unsafe_math(float(all))]
+pub fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+#[unsafe_math]
+pub fn algebraic(a: f64, b: f64) -> f64 {
+    -(a * b) / 2.0 % 7.0
+}
+
+#[unsafe_math(policy = wrapping)]
+pub fn hash(bytes: &[u8]) -> u32 {
+    let mut hash = 0x811c9dc5u32;
+    for &byte in bytes {
+        hash ^= byte as u32;
+        hash *= 0x01000193;
+    }
+    hash
+}
+
+#[unsafe_math(policy = saturating)]
+pub fn brighten(pixel: u8, amount: u8) -> u8 {
+    pixel + amount
+}
+
+#[unsafe_math(policy = checked)]
+pub fn checked_add(a: u16, b: u16) -> u16 {
+    a + b
+}
+
+#[unsafe_math(policy = trap)]
+pub fn trapped_add(a: u16, b: u16) -> u16 {
+    a + b
+}
+
+pub fn block(a: u64, b: u64) -> u64 {
+    unsafe_math_block! {
+        #![unsafe_math(not(div))]
+        (a * b + 1) / b
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, UnsafeMath)]
+pub struct Fixed(pub i32);
+
+impl core::ops::Add for Fixed {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Fixed(self.0 + rhs.0)
+    }
+}
+
+#[unsafe_math]
+pub fn fixed_sum(a: Fixed, b: Fixed) -> Fixed {
+    a + b
+}
+
+#[cfg(test)]
+mod tests {
+    // not at the crate root, that would put `std` into the extern prelude and hide bad paths
+    extern crate std;
+
+    use super::*;
+
+    #[test]
+    fn test_expansions() {
+        assert_eq!(dot(&[1, 2, 3, 4], &[5, 6, 7, 8]), 70);
+        assert_eq!(sum_slice(&[1, 2, 3, 4], 3), 6);
+        assert_eq!(methods(-7, 3), (49, 7, 2, 249));
+        assert_eq!(lerp(1.0, 3.0, 0.5), 2.0);
+        assert_eq!(algebraic(3.0, 4.0), -6.0);
+        assert_eq!(hash(b"a"), 0xe40c292c);
+        assert_eq!(brighten(250, 10), 255);
+        assert_eq!(checked_add(1, 2), 3);
+        assert_eq!(trapped_add(1, 2), 3);
+        assert_eq!(block(6, 7), 6);
+        assert_eq!(fixed_sum(Fixed(1), Fixed(2)), Fixed(3));
+    }
+
+    #[test]
+    #[should_panic(expected = "unsafe_math")]
+    fn test_checked_policy() {
+        checked_add(u16::MAX, std::hint::black_box(1));
+    }
+}
@@ -0,0 +1,21 @@
+//! Detects nightly toolchain: fast-math float intrinsics and unchecked division and remainder are only available there.
+//! On stable, operations fall back to plain ones with UB cases assumed away
+
+use std::env;
+use std::process::Command;
+
+fn main() {
+    println!("cargo::rustc-check-cfg=cfg(unsafe_math_nightly)");
+    println!("cargo::rerun-if-env-changed=RUSTC");
+
+    let rustc = env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    let version = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+        .unwrap_or_default();
+    // `rustc 1.90.0-nightly (...)`, locally built compilers are `-dev`
+    if version.contains("-nightly") || version.contains("-dev") {
+        println!("cargo::rustc-cfg=unsafe_math_nightly");
+    }
+}
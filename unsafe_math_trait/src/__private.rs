@@ -0,0 +1,1386 @@
+//! Functions called by code generated by the `unsafe_math` macro. Not a public API.
+//!
+//! Every rewritten operator goes through here, so the choice between checked and unchecked versions lives in one place.
+//! `mode` argument comes from `policy` of the macro, see [`Mode`].
+//!
+//! Macro can't see types, so it does not know whether operands have fast versions of an operator (`FastAdd`, `FastMul`...).
+//! Instead, generated code picks implementation with autoref specialization:
+//! ```ignore
+//! (&Probe::new(&lhs, &rhs)).add_kind().add(lhs, rhs, mode, "lhs + rhs")
+//! ```
+//! `add_kind` of [`FastAddKind`] is implemented for `Probe<L, R>` where `L: FastAdd<R> + Add<R>`, and is found first (no autoref needed).
+//! Otherwise method resolution autorefs and finds [`OpsAddKind`] on `&Probe<L, R>`, which falls back to plain `std::ops`.
+//! Binary operators have one more step in front, [`SameAddKind`] for operands of the same type
+//! ([`SameShlKind`] for a shift that gives the left operand's type),
+//! so these are shifted by one: `&Probe<L, R>` for [`FastAddKind`] and `Probe<L, R>` by value for [`OpsAddKind`].
+//!
+//! Method calls (`x.pow(3)`) can't fall back to a generic function, since the original method may be anything.
+//! Generated code keeps the original call in a branch that is taken only for [`OpsTag`]:
+//! ```ignore
+//! let tag = (&Probe::new(&x, &())).pow_kind();
+//! if tag.is_fast(mode) { tag.pow(&x, (3,), mode, "x.pow(3)") } else { x.pow(3) }
+//! ```
+//!
+//! Compound assignment to a variable or its fields, or of a literal or variable, is probed the same way after evaluating
+//! both operands, evaluation order can't be seen there. Otherwise it depends on the types: builtin `a[i] += f()` evaluates `f()` first,
+//! overloaded one evaluates `a[i]` first. Operand types come from a branch that never runs, and [`RhsFirst`]
+//! or [`PlaceFirst`] picks the order. The branch diverges before mentioning operands, so they are not borrow checked there:
+//! ```ignore
+//! let probe = Probe::unknown();
+//! if false { unreachable(); probe.infer(&a[i], &f()); }
+//! let (tag, order) = ((&probe).add_assign_kind(), (&probe).assign_order());
+//! if order.rhs_first() {
+//!     (order.enter_rhs_first())();
+//!     match f() { rhs => tag.add_assign(&mut a[i], rhs, mode, "a[i] += f()") }
+//! } else {
+//!     (order.enter_place_first())();
+//!     match a[i].unsafe_math_operands(f()) { (place, rhs) => tag.add_assign(place, rhs, mode, "a[i] += f()") }
+//! }
+//! ```
+
+//! `wrapping` and `saturating` policies probe with their own kinds (`wrapping_add_kind`), that find [`WrappingTag`]
+//! or [`SaturatingTag`] for integers and fall back to [`OpsTag`] for everything else.
+//!
+//! `int`, `float` and `types(...)` probe with `only_*` kinds (`only_add_kind`). They find [`OnlyTag`] for types
+//! that know their [`Scalars`], which does the fast operation if all of them are in [`Mode`]'s types,
+//! and the plain one otherwise.
+//!
+//! `assume(...)` conditions don't need any of that, they are passed to [`Mode::assume`] as plain `bool`s.
+
+use crate::{
+    FastAbs, FastAdd, FastCast, FastDiv, FastEuclid, FastFloat, FastMul, FastNeg, FastPow, FastRem, FastShl, FastShr,
+    FastSub, FloatFlags, SaturatingMath, ShiftAmount, WrappingMath,
+};
+use core::marker::PhantomData;
+use core::ops::{
+    Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Rem, RemAssign, Shl, ShlAssign, Shr,
+    ShrAssign, Sub, SubAssign,
+};
+use core::panic::Location;
+use core::slice::SliceIndex;
+
+const FORCE_CHECKED: bool = cfg!(unsafe_math_checked);
+
+/// Whether fast operations are built on nightly intrinsics, see build.rs
+pub const NIGHTLY: bool = cfg!(unsafe_math_nightly);
+
+#[cold]
+#[inline(never)]
+#[track_caller]
+fn undefined_behaviour(op: &str, expr: &str) -> ! {
+    let location = Location::caller();
+    panic!("unsafe_math: `{expr}` at {location} is undefined behaviour: {op} overflowed or got invalid operands");
+}
+
+/// Trap instruction on nightly, `abort` otherwise.
+/// Without std on stable there is no abort, so this is not one: an undefined instruction where inline asm has it
+/// (a fault the platform handles, same as the nightly intrinsic). Other targets panic with a fixed message,
+/// nothing is formatted but the panic handler runs
+#[inline(always)]
+fn trap() -> ! {
+    #[cfg(unsafe_math_nightly)]
+    core::intrinsics::abort();
+    #[cfg(all(not(unsafe_math_nightly), feature = "std"))]
+    std::process::abort();
+    #[cfg(all(not(unsafe_math_nightly), not(feature = "std"), any(target_arch = "x86", target_arch = "x86_64")))]
+    unsafe {
+        core::arch::asm!("ud2", options(noreturn, nomem, nostack))
+    }
+    #[cfg(all(not(unsafe_math_nightly), not(feature = "std"), any(target_arch = "arm", target_arch = "aarch64")))]
+    unsafe {
+        core::arch::asm!("udf #0", options(noreturn, nomem, nostack))
+    }
+    #[cfg(all(not(unsafe_math_nightly), not(feature = "std"), any(target_arch = "riscv32", target_arch = "riscv64")))]
+    unsafe {
+        core::arch::asm!("unimp", options(noreturn, nomem, nostack))
+    }
+    #[cfg(all(not(unsafe_math_nightly), not(feature = "std"), target_arch = "wasm32"))]
+    core::arch::wasm32::unreachable();
+    #[cfg(all(
+        not(unsafe_math_nightly),
+        not(feature = "std"),
+        not(any(
+            target_arch = "x86",
+            target_arch = "x86_64",
+            target_arch = "arm",
+            target_arch = "aarch64",
+            target_arch = "riscv32",
+            target_arch = "riscv64",
+            target_arch = "wasm32"
+        ))
+    ))]
+    panic!("unsafe_math: trap");
+}
+
+#[cold]
+#[inline(never)]
+#[track_caller]
+fn out_of_bounds(expr: &str) -> ! {
+    let location = Location::caller();
+    panic!("unsafe_math: `{expr}` at {location} is undefined behaviour: index out of bounds");
+}
+
+#[cold]
+#[inline(never)]
+#[track_caller]
+fn assumption_failed(expr: &str) -> ! {
+    let location = Location::caller();
+    panic!("unsafe_math: assumption `{expr}` at {location} does not hold");
+}
+
+/// What fast operations do about UB, which float fast-math flags they use (sound ones unless set with `float(...)`),
+/// and which types get them at all
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Mode {
+    checks: Checks,
+    float: FloatFlags,
+    types: Types,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Checks {
+    /// Assume UB doesn't happen
+    Off,
+    /// Check and panic with the expression and its location
+    Panic,
+    /// Check and abort, without formatting anything
+    Trap,
+}
+
+impl Mode {
+    /// `policy = checked`
+    pub const CHECKED: Mode = Mode { checks: Checks::Panic, float: FloatFlags::ALGEBRAIC, types: Types::ALL };
+    /// `policy = trap`
+    pub const TRAP: Mode = Mode { checks: Checks::Trap, float: FloatFlags::ALGEBRAIC, types: Types::ALL };
+
+    /// Default `unchecked` policy. `debug` is `cfg!(debug_assertions)` of the crate where macro was expanded,
+    /// checks are on in debug builds, and with `--cfg unsafe_math_checked` (e.g. for optimized test runs)
+    #[inline(always)]
+    pub const fn unchecked(debug: bool) -> Mode {
+        let checks = if debug || FORCE_CHECKED { Checks::Panic } else { Checks::Off };
+        Mode { checks, float: FloatFlags::ALGEBRAIC, types: Types::ALL }
+    }
+
+    /// Float fast-math flags of `float(...)`
+    #[inline(always)]
+    pub const fn float(self, float: FloatFlags) -> Mode {
+        Mode { float, ..self }
+    }
+
+    /// Types of `int`, `float` and `types(...)`, used by [`OnlyTag`]
+    #[inline(always)]
+    pub const fn only(self, types: Types) -> Mode {
+        Mode { types, ..self }
+    }
+
+    /// Condition of `assume(...)`, called at the top of the fn or block. Asserted when checks are on,
+    /// otherwise optimizer relies on it, so a false one is UB
+    #[inline(always)]
+    #[track_caller]
+    pub fn assume(self, condition: bool, expr: &'static str) {
+        if self.checks() {
+            if !condition {
+                self.fail_assumption(expr);
+            }
+        } else {
+            unsafe { core::hint::assert_unchecked(condition) }
+        }
+    }
+
+    #[inline(always)]
+    fn checks(self) -> bool {
+        !matches!(self.checks, Checks::Off)
+    }
+
+    #[inline(always)]
+    #[track_caller]
+    fn fail(self, op: &str, expr: &str) -> ! {
+        match self.checks {
+            Checks::Trap => trap(),
+            _ => undefined_behaviour(op, expr),
+        }
+    }
+
+    #[inline(always)]
+    #[track_caller]
+    fn fail_index(self, expr: &str) -> ! {
+        match self.checks {
+            Checks::Trap => trap(),
+            _ => out_of_bounds(expr),
+        }
+    }
+
+    #[inline(always)]
+    #[track_caller]
+    fn fail_assumption(self, expr: &str) -> ! {
+        match self.checks {
+            Checks::Trap => trap(),
+            _ => assumption_failed(expr),
+        }
+    }
+}
+
+/// Primitive types of `int`, `float` and `types(...)`, tiny bitset
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Types(u16);
+
+impl Types {
+    pub const NONE: Types = Types(0);
+    pub const I8: Types = Types(1 << 0);
+    pub const U8: Types = Types(1 << 1);
+    pub const I16: Types = Types(1 << 2);
+    pub const U16: Types = Types(1 << 3);
+    pub const I32: Types = Types(1 << 4);
+    pub const U32: Types = Types(1 << 5);
+    pub const I64: Types = Types(1 << 6);
+    pub const U64: Types = Types(1 << 7);
+    pub const I128: Types = Types(1 << 8);
+    pub const U128: Types = Types(1 << 9);
+    pub const ISIZE: Types = Types(1 << 10);
+    pub const USIZE: Types = Types(1 << 11);
+    pub const F32: Types = Types(1 << 12);
+    pub const F64: Types = Types(1 << 13);
+    /// `int`
+    pub const INT: Types = Types((1 << 12) - 1);
+    /// `float`
+    pub const FLOAT: Types = Types::F32.union(Types::F64);
+    pub const ALL: Types = Types::INT.union(Types::FLOAT);
+
+    pub const fn union(self, other: Types) -> Types {
+        Types(self.0 | other.0)
+    }
+
+    pub const fn contains(self, other: Types) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+/// Primitive types a type is made of. With `int`, `float` or `types(...)` a type gets fast operations
+/// only if it implements this and all of its scalars are listed, vek and derived types included
+pub trait Scalars {
+    const TYPES: Types;
+}
+
+macro_rules! impl_scalars {
+    ($($t:ty => $types:ident),*) => {
+        $(
+            impl Scalars for $t {
+                const TYPES: Types = Types::$types;
+            }
+
+            impl Primitive for $t {}
+        )*
+    };
+}
+
+impl_scalars!(
+    i8 => I8, u8 => U8, i16 => I16, u16 => U16, i32 => I32, u32 => U32, i64 => I64, u64 => U64,
+    i128 => I128, u128 => U128, isize => ISIZE, usize => USIZE, f32 => F32, f64 => F64
+);
+
+impl<T: Scalars + ?Sized> Scalars for &T {
+    const TYPES: Types = T::TYPES;
+}
+
+impl<T: Scalars, const N: usize> Scalars for [T; N] {
+    const TYPES: Types = T::TYPES;
+}
+
+/// Shift amount for `#[derive(UnsafeMath)]` with `via_ops`, amounts that don't fit `u32` are UB like in `fast_shl`
+#[inline(always)]
+pub fn shift_amount<R: ShiftAmount>(rhs: R) -> u32 {
+    unsafe { crate::assume_some(rhs.shift_amount()) }
+}
+
+/// Carries operand types for picking implementation, see module docs
+pub struct Probe<L: ?Sized, R: ?Sized>(PhantomData<(*const L, *const R)>);
+
+impl<L: ?Sized, R: ?Sized> Probe<L, R> {
+    #[inline(always)]
+    pub fn new(_lhs: &L, _rhs: &R) -> Self {
+        Probe(PhantomData)
+    }
+
+    /// For when R is only known as a type, e.g. target type of a cast: `Probe::<_, u8>::of(&x)`
+    #[inline(always)]
+    pub fn of(_lhs: &L) -> Self {
+        Probe(PhantomData)
+    }
+
+    /// For compound assignment, which must pick the order of evaluation before evaluating anything.
+    /// Types are given by [`Probe::infer`] in a branch that never runs
+    #[inline(always)]
+    pub fn unknown() -> Self {
+        Probe(PhantomData)
+    }
+
+    #[inline(always)]
+    pub fn infer(&self, _lhs: &L, _rhs: &R) {}
+}
+
+// by value receiver of `Ops*Kind` impls, see module docs
+impl<L: ?Sized, R: ?Sized> Clone for Probe<L, R> {
+    #[inline(always)]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<L: ?Sized, R: ?Sized> Copy for Probe<L, R> {}
+
+/// Numbers with builtin operators
+pub trait Primitive {}
+
+/// Builtin compound assignment, both operands are [`Primitive`]. Evaluates the right side first
+pub struct RhsFirst;
+
+/// Overloaded compound assignment. Evaluates the place first, borrowing it like `place.add_assign(rhs)` would
+pub struct PlaceFirst;
+
+pub trait RhsFirstKind {
+    fn assign_order(&self) -> RhsFirst;
+}
+
+impl<L: Primitive, R: Primitive> RhsFirstKind for Probe<L, R> {
+    #[inline(always)]
+    fn assign_order(&self) -> RhsFirst {
+        RhsFirst
+    }
+}
+
+pub trait PlaceFirstKind {
+    fn assign_order(&self) -> PlaceFirst;
+}
+
+impl<L, R> PlaceFirstKind for &Probe<L, R> {
+    #[inline(always)]
+    fn assign_order(&self) -> PlaceFirst {
+        PlaceFirst
+    }
+}
+
+/// Cuts off code that is only there for its types, see module docs
+pub fn unreachable() -> ! {
+    unreachable!("branch of the other evaluation order")
+}
+
+// Each branch of compound assignment starts with calling `enter_*()`. It is `fn() -> !` for the branch of the other order,
+// so the code after it is unreachable and not borrow checked: `v[0] += v[1]` is fine for `u32`, and an error for `Vec3`
+impl RhsFirst {
+    #[inline(always)]
+    pub fn rhs_first(&self) -> bool {
+        true
+    }
+
+    #[inline(always)]
+    pub fn enter_rhs_first(&self) -> fn() {
+        || {}
+    }
+
+    #[inline(always)]
+    pub fn enter_place_first(&self) -> fn() -> ! {
+        unreachable
+    }
+}
+
+impl PlaceFirst {
+    #[inline(always)]
+    pub fn rhs_first(&self) -> bool {
+        false
+    }
+
+    #[inline(always)]
+    pub fn enter_rhs_first(&self) -> fn() -> ! {
+        unreachable
+    }
+
+    #[inline(always)]
+    pub fn enter_place_first(&self) -> fn() {
+        || {}
+    }
+}
+
+/// Borrows the place before evaluating the right side, called as `place.unsafe_math_operands(rhs)` for a two-phase borrow.
+/// Name is long, since inherent methods of the place type would be found first
+pub trait AssignOperands {
+    #[inline(always)]
+    fn unsafe_math_operands<R>(&mut self, rhs: R) -> (&mut Self, R) {
+        (self, rhs)
+    }
+}
+
+impl<T: ?Sized> AssignOperands for T {}
+
+/// Operands have a fast version of the operation, use it (or checked version)
+pub struct FastTag;
+
+/// Operands have no fast version, use regular operators
+pub struct OpsTag;
+
+/// Operands have a fast version, used if their scalars are in `mode` (`int`, `float` and `types(...)`)
+pub struct OnlyTag(Types);
+
+/// Operands are of the same type and the result is of that type too. Same as [`OnlyTag`] otherwise,
+/// with [`Types::NONE`] when types are not limited
+pub struct SameTag(Types);
+
+// Kinds are tried in three steps, so binary operators are generic over `R` only when operands have different types:
+// `Same*Kind` for `Probe<L, L>`, `Fast*Kind` for `&Probe<L, R>`, `Ops*Kind` for `Probe<L, R>` by value.
+// Mixed operand impls (`i32: FastMul<Vec2<i32>>`) would make `let a = 10; a * 3` ambiguous otherwise,
+// and integer fallback would pick `i32` even when the result is used as `u16`. Shifts have their own `Same*Kind`, see below
+macro_rules! dispatch {
+    ($(
+        $name:ident, $name_assign:ident where [$($bounds:tt)*] -> $out:ty, [$($assign_bounds:tt)*]
+            => $fast:ident($($flags:ident)?), $try_fast:ident,
+        $std_op:ident::$std_fn:ident, $std_op_assign:ident::$std_fn_assign:ident,
+        $([same: $fast_trait:ident, $same_kind:ident, $only_same_kind:ident])?
+        $fast_kind:ident::$kind:ident, $ops_kind:ident,
+        $only_fast_kind:ident::$only_kind:ident, $only_ops_kind:ident,
+        $fast_assign_kind:ident::$assign_kind:ident, $ops_assign_kind:ident,
+        $only_fast_assign_kind:ident::$only_assign_kind:ident, $only_ops_assign_kind:ident;
+    )*) => {
+        $(
+            $(
+                pub trait $same_kind {
+                    fn $kind(&self) -> SameTag;
+                }
+
+                impl<L> $same_kind for Probe<L, L>
+                where
+                    L: $std_op<L, Output = L> + $fast_trait<L, Output = L>,
+                {
+                    #[inline(always)]
+                    fn $kind(&self) -> SameTag {
+                        SameTag(Types::NONE)
+                    }
+                }
+
+                pub trait $only_same_kind {
+                    fn $only_kind(&self) -> SameTag;
+                }
+
+                impl<L> $only_same_kind for Probe<L, L>
+                where
+                    L: Scalars + $std_op<L, Output = L> + $fast_trait<L, Output = L>,
+                {
+                    #[inline(always)]
+                    fn $only_kind(&self) -> SameTag {
+                        SameTag(L::TYPES)
+                    }
+                }
+
+                impl SameTag {
+                    #[inline(always)]
+                    #[track_caller]
+                    pub fn $name<L, R>(self, lhs: L, rhs: R, mode: Mode, expr: &'static str) -> L
+                    where
+                        L: $std_op<R, Output = L> + $fast_trait<R, Output = L>,
+                    {
+                        match mode.types.contains(self.0) {
+                            true => FastTag.$name(lhs, rhs, mode, expr),
+                            false => $std_op::$std_fn(lhs, rhs),
+                        }
+                    }
+                }
+            )?
+
+            pub trait $fast_kind {
+                fn $kind(&self) -> FastTag;
+            }
+
+            // std operator is required too, so that fast path never accepts what plain Rust wouldn't
+            impl<L, R> $fast_kind for &Probe<L, R>
+            where
+                L: $std_op<R>,
+                $($bounds)*
+            {
+                #[inline(always)]
+                fn $kind(&self) -> FastTag {
+                    FastTag
+                }
+            }
+
+            pub trait $ops_kind {
+                fn $kind(self) -> OpsTag;
+            }
+
+            impl<L, R> $ops_kind for Probe<L, R> {
+                #[inline(always)]
+                fn $kind(self) -> OpsTag {
+                    OpsTag
+                }
+            }
+
+            // `place op= rhs` is done as `*place = *place op rhs`, so the fast path needs a place that can be copied
+            // and holds the result. Other types keep their own compound assignment
+            pub trait $fast_assign_kind {
+                fn $assign_kind(&self) -> FastTag;
+            }
+
+            impl<L, R> $fast_assign_kind for Probe<L, R>
+            where
+                L: Copy + $std_op_assign<R>,
+                $($assign_bounds)*
+            {
+                #[inline(always)]
+                fn $assign_kind(&self) -> FastTag {
+                    FastTag
+                }
+            }
+
+            pub trait $ops_assign_kind {
+                fn $assign_kind(&self) -> OpsTag;
+            }
+
+            impl<L, R> $ops_assign_kind for &Probe<L, R> {
+                #[inline(always)]
+                fn $assign_kind(&self) -> OpsTag {
+                    OpsTag
+                }
+            }
+
+            pub trait $only_fast_assign_kind {
+                fn $only_assign_kind(&self) -> OnlyTag;
+            }
+
+            impl<L, R> $only_fast_assign_kind for Probe<L, R>
+            where
+                L: Scalars + Copy + $std_op_assign<R> + $std_op<R, Output = L>,
+                $($assign_bounds)*
+            {
+                #[inline(always)]
+                fn $only_assign_kind(&self) -> OnlyTag {
+                    OnlyTag(L::TYPES)
+                }
+            }
+
+            pub trait $only_ops_assign_kind {
+                fn $only_assign_kind(&self) -> OpsTag;
+            }
+
+            impl<L, R> $only_ops_assign_kind for &Probe<L, R> {
+                #[inline(always)]
+                fn $only_assign_kind(&self) -> OpsTag {
+                    OpsTag
+                }
+            }
+
+            impl FastTag {
+                #[inline(always)]
+                #[track_caller]
+                pub fn $name<L, R>(self, lhs: L, rhs: R, mode: Mode, expr: &'static str) -> $out
+                where
+                    $($bounds)*
+                {
+                    if mode.checks() {
+                        match lhs.$try_fast(rhs $(, mode.$flags)?) {
+                            Some(result) => result,
+                            None => mode.fail(stringify!($name), expr),
+                        }
+                    } else {
+                        lhs.$fast(rhs $(, mode.$flags)?)
+                    }
+                }
+
+                #[inline(always)]
+                #[track_caller]
+                pub fn $name_assign<L, R>(self, place: &mut L, rhs: R, mode: Mode, expr: &'static str)
+                where
+                    L: Copy,
+                    $($assign_bounds)*
+                {
+                    *place = self.$name(*place, rhs, mode, expr);
+                }
+            }
+
+            impl OpsTag {
+                #[inline(always)]
+                pub fn $name<L: $std_op<R>, R>(self, lhs: L, rhs: R, _mode: Mode, _expr: &'static str) -> L::Output {
+                    $std_op::$std_fn(lhs, rhs)
+                }
+
+                #[inline(always)]
+                pub fn $name_assign<L: $std_op_assign<R>, R>(self, place: &mut L, rhs: R, _mode: Mode, _expr: &'static str) {
+                    $std_op_assign::$std_fn_assign(place, rhs)
+                }
+            }
+
+            pub trait $only_fast_kind {
+                fn $only_kind(&self) -> OnlyTag;
+            }
+
+            // both versions must give the same type, the choice between them is made by value of `mode`
+            impl<L, R> $only_fast_kind for &Probe<L, R>
+            where
+                L: Scalars + $std_op<R, Output = $out>,
+                $($bounds)*
+            {
+                #[inline(always)]
+                fn $only_kind(&self) -> OnlyTag {
+                    OnlyTag(L::TYPES)
+                }
+            }
+
+            pub trait $only_ops_kind {
+                fn $only_kind(self) -> OpsTag;
+            }
+
+            impl<L, R> $only_ops_kind for Probe<L, R> {
+                #[inline(always)]
+                fn $only_kind(self) -> OpsTag {
+                    OpsTag
+                }
+            }
+            impl OnlyTag {
+                #[inline(always)]
+                #[track_caller]
+                pub fn $name<L, R>(self, lhs: L, rhs: R, mode: Mode, expr: &'static str) -> $out
+                where
+                    L: $std_op<R, Output = $out>,
+                    $($bounds)*
+                {
+                    match mode.types.contains(self.0) {
+                        true => FastTag.$name(lhs, rhs, mode, expr),
+                        false => $std_op::$std_fn(lhs, rhs),
+                    }
+                }
+
+                #[inline(always)]
+                #[track_caller]
+                pub fn $name_assign<L, R>(self, place: &mut L, rhs: R, mode: Mode, expr: &'static str)
+                where
+                    L: Copy + $std_op<R, Output = L>,
+                    $($assign_bounds)*
+                {
+                    *place = match mode.types.contains(self.0) {
+                        true => FastTag.$name(*place, rhs, mode, expr),
+                        false => $std_op::$std_fn(*place, rhs),
+                    };
+                }
+            }
+        )*
+    };
+}
+
+dispatch! {
+    add, add_assign where [L: FastAdd<R>] -> <L as FastAdd<R>>::Output, [L: FastAdd<R, Output = L>]
+        => fast_add_with(float), try_fast_add_with, Add::add, AddAssign::add_assign,
+        [same: FastAdd, SameAddKind, SameOnlyAddKind] FastAddKind::add_kind, OpsAddKind,
+        FastOnlyAddKind::only_add_kind, OpsOnlyAddKind,
+        FastAddAssignKind::add_assign_kind, OpsAddAssignKind,
+        FastOnlyAddAssignKind::only_add_assign_kind, OpsOnlyAddAssignKind;
+    sub, sub_assign where [L: FastSub<R>] -> <L as FastSub<R>>::Output, [L: FastSub<R, Output = L>]
+        => fast_sub_with(float), try_fast_sub_with, Sub::sub, SubAssign::sub_assign,
+        [same: FastSub, SameSubKind, SameOnlySubKind] FastSubKind::sub_kind, OpsSubKind,
+        FastOnlySubKind::only_sub_kind, OpsOnlySubKind,
+        FastSubAssignKind::sub_assign_kind, OpsSubAssignKind,
+        FastOnlySubAssignKind::only_sub_assign_kind, OpsOnlySubAssignKind;
+    mul, mul_assign where [L: FastMul<R>] -> <L as FastMul<R>>::Output, [L: FastMul<R, Output = L>]
+        => fast_mul_with(float), try_fast_mul_with, Mul::mul, MulAssign::mul_assign,
+        [same: FastMul, SameMulKind, SameOnlyMulKind] FastMulKind::mul_kind, OpsMulKind,
+        FastOnlyMulKind::only_mul_kind, OpsOnlyMulKind,
+        FastMulAssignKind::mul_assign_kind, OpsMulAssignKind,
+        FastOnlyMulAssignKind::only_mul_assign_kind, OpsOnlyMulAssignKind;
+    div, div_assign where [L: FastDiv<R>] -> <L as FastDiv<R>>::Output, [L: FastDiv<R, Output = L>]
+        => fast_div_with(float), try_fast_div_with, Div::div, DivAssign::div_assign,
+        [same: FastDiv, SameDivKind, SameOnlyDivKind] FastDivKind::div_kind, OpsDivKind,
+        FastOnlyDivKind::only_div_kind, OpsOnlyDivKind,
+        FastDivAssignKind::div_assign_kind, OpsDivAssignKind,
+        FastOnlyDivAssignKind::only_div_assign_kind, OpsOnlyDivAssignKind;
+    rem, rem_assign where [L: FastRem<R>] -> <L as FastRem<R>>::Output, [L: FastRem<R, Output = L>]
+        => fast_rem_with(float), try_fast_rem_with, Rem::rem, RemAssign::rem_assign,
+        [same: FastRem, SameRemKind, SameOnlyRemKind] FastRemKind::rem_kind, OpsRemKind,
+        FastOnlyRemKind::only_rem_kind, OpsOnlyRemKind,
+        FastRemAssignKind::rem_assign_kind, OpsRemAssignKind,
+        FastOnlyRemAssignKind::only_rem_assign_kind, OpsOnlyRemAssignKind;
+    shl, shl_assign where [L: FastShl, R: ShiftAmount] -> <L as FastShl>::Output, [L: FastShl<Output = L>, R: ShiftAmount]
+        => fast_shl(), try_fast_shl, Shl::shl, ShlAssign::shl_assign, FastShlKind::shl_kind, OpsShlKind,
+        FastOnlyShlKind::only_shl_kind, OpsOnlyShlKind,
+        FastShlAssignKind::shl_assign_kind, OpsShlAssignKind,
+        FastOnlyShlAssignKind::only_shl_assign_kind, OpsOnlyShlAssignKind;
+    shr, shr_assign where [L: FastShr, R: ShiftAmount] -> <L as FastShr>::Output, [L: FastShr<Output = L>, R: ShiftAmount]
+        => fast_shr(), try_fast_shr, Shr::shr, ShrAssign::shr_assign, FastShrKind::shr_kind, OpsShrKind,
+        FastOnlyShrKind::only_shr_kind, OpsOnlyShrKind,
+        FastShrAssignKind::shr_assign_kind, OpsShrAssignKind,
+        FastOnlyShrAssignKind::only_shr_assign_kind, OpsOnlyShrAssignKind;
+}
+
+// shift amount is of any type, so `Same*Kind` of shifts is for `Probe<L, R>` with a result of type `L`.
+// `&x << 1` gives `x`'s type through `FastShl::Output` in `Fast*Kind`, which integer literals can't be inferred through
+macro_rules! dispatch_same_shift {
+    ($($name:ident: $fast_trait:ident, $std_op:ident::$std_fn:ident, $same_kind:ident::$kind:ident, $only_same_kind:ident::$only_kind:ident;)*) => {
+        $(
+            pub trait $same_kind {
+                fn $kind(&self) -> SameTag;
+            }
+
+            impl<L, R: ShiftAmount> $same_kind for Probe<L, R>
+            where
+                L: $std_op<R, Output = L> + $fast_trait<Output = L>,
+            {
+                #[inline(always)]
+                fn $kind(&self) -> SameTag {
+                    SameTag(Types::NONE)
+                }
+            }
+
+            pub trait $only_same_kind {
+                fn $only_kind(&self) -> SameTag;
+            }
+
+            impl<L, R: ShiftAmount> $only_same_kind for Probe<L, R>
+            where
+                L: Scalars + $std_op<R, Output = L> + $fast_trait<Output = L>,
+            {
+                #[inline(always)]
+                fn $only_kind(&self) -> SameTag {
+                    SameTag(L::TYPES)
+                }
+            }
+
+            impl SameTag {
+                #[inline(always)]
+                #[track_caller]
+                pub fn $name<L, R: ShiftAmount>(self, lhs: L, rhs: R, mode: Mode, expr: &'static str) -> L
+                where
+                    L: $std_op<R, Output = L> + $fast_trait<Output = L>,
+                {
+                    match mode.types.contains(self.0) {
+                        true => FastTag.$name(lhs, rhs, mode, expr),
+                        false => $std_op::$std_fn(lhs, rhs),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+dispatch_same_shift! {
+    shl: FastShl, Shl::shl, SameShlKind::shl_kind, SameOnlyShlKind::only_shl_kind;
+    shr: FastShr, Shr::shr, SameShrKind::shr_kind, SameOnlyShrKind::only_shr_kind;
+}
+
+// unary minus, probed as `Probe<T, ()>`
+
+pub trait FastNegKind {
+    fn neg_kind(&self) -> FastTag;
+}
+
+impl<T: FastNeg> FastNegKind for Probe<T, ()> {
+    #[inline(always)]
+    fn neg_kind(&self) -> FastTag {
+        FastTag
+    }
+}
+
+pub trait OpsNegKind {
+    fn neg_kind(&self) -> OpsTag;
+}
+
+impl<L, R> OpsNegKind for &Probe<L, R> {
+    #[inline(always)]
+    fn neg_kind(&self) -> OpsTag {
+        OpsTag
+    }
+}
+
+impl FastTag {
+    #[inline(always)]
+    #[track_caller]
+    pub fn neg<T: FastNeg>(self, operand: T, mode: Mode, expr: &'static str) -> T {
+        if mode.checks() {
+            match operand.try_fast_neg_with(mode.float) {
+                Some(result) => result,
+                None => mode.fail("neg", expr),
+            }
+        } else {
+            operand.fast_neg_with(mode.float)
+        }
+    }
+}
+
+impl OpsTag {
+    #[inline(always)]
+    // for unsigned integers this is where `the trait Neg is not implemented for u32` comes from, pointing at `-x`
+    pub fn neg<T: Neg>(self, operand: T, _mode: Mode, _expr: &'static str) -> T::Output {
+        Neg::neg(operand)
+    }
+}
+
+pub trait FastOnlyNegKind {
+    fn only_neg_kind(&self) -> OnlyTag;
+}
+
+impl<T: FastNeg + Scalars + Neg<Output = T>> FastOnlyNegKind for Probe<T, ()> {
+    #[inline(always)]
+    fn only_neg_kind(&self) -> OnlyTag {
+        OnlyTag(T::TYPES)
+    }
+}
+
+pub trait OpsOnlyNegKind {
+    fn only_neg_kind(&self) -> OpsTag;
+}
+
+impl<L, R> OpsOnlyNegKind for &Probe<L, R> {
+    #[inline(always)]
+    fn only_neg_kind(&self) -> OpsTag {
+        OpsTag
+    }
+}
+
+impl OnlyTag {
+    #[inline(always)]
+    #[track_caller]
+    pub fn neg<T: FastNeg + Neg<Output = T>>(self, operand: T, mode: Mode, expr: &'static str) -> T {
+        match mode.types.contains(self.0) {
+            true => FastTag.neg(operand, mode, expr),
+            false => Neg::neg(operand),
+        }
+    }
+}
+
+/// Operands have a wrapping version of the operation (`policy = wrapping`)
+pub struct WrappingTag;
+
+/// Operands have a saturating version of the operation (`policy = saturating`)
+pub struct SaturatingTag;
+
+// same shape as `dispatch!`, without checks: wrapping and saturating operations have no UB.
+// Operands are of the same type `T`, except for shift amounts
+macro_rules! dispatch_policy {
+    ($policy:ident: $tag:ident; $(
+        $name:ident, $name_assign:ident [$($generics:tt)*] $rhs:ty => $func:ident, $std_op:ident,
+        $fast_kind:ident::$kind:ident, $ops_kind:ident;
+    )*) => {
+        $(
+            pub trait $fast_kind {
+                fn $kind(&self) -> $tag;
+            }
+
+            impl<T, $($generics)*> $fast_kind for Probe<T, $rhs>
+            where
+                T: $policy + $std_op<$rhs>,
+            {
+                #[inline(always)]
+                fn $kind(&self) -> $tag {
+                    $tag
+                }
+            }
+
+            pub trait $ops_kind {
+                fn $kind(&self) -> OpsTag;
+            }
+
+            impl<L, R> $ops_kind for &Probe<L, R> {
+                #[inline(always)]
+                fn $kind(&self) -> OpsTag {
+                    OpsTag
+                }
+            }
+
+            impl $tag {
+                #[inline(always)]
+                pub fn $name<T: $policy, $($generics)*>(self, lhs: T, rhs: $rhs, _mode: Mode, _expr: &'static str) -> T {
+                    $policy::$func(lhs, rhs)
+                }
+
+                #[inline(always)]
+                pub fn $name_assign<T: $policy + Copy, $($generics)*>(self, place: &mut T, rhs: $rhs, mode: Mode, expr: &'static str) {
+                    *place = self.$name(*place, rhs, mode, expr);
+                }
+            }
+        )*
+    };
+}
+
+dispatch_policy! { WrappingMath: WrappingTag;
+    add, add_assign [] T => wrapping_add, Add, FastWrappingAddKind::wrapping_add_kind, OpsWrappingAddKind;
+    sub, sub_assign [] T => wrapping_sub, Sub, FastWrappingSubKind::wrapping_sub_kind, OpsWrappingSubKind;
+    mul, mul_assign [] T => wrapping_mul, Mul, FastWrappingMulKind::wrapping_mul_kind, OpsWrappingMulKind;
+    div, div_assign [] T => wrapping_div, Div, FastWrappingDivKind::wrapping_div_kind, OpsWrappingDivKind;
+    rem, rem_assign [] T => wrapping_rem, Rem, FastWrappingRemKind::wrapping_rem_kind, OpsWrappingRemKind;
+    shl, shl_assign [R: ShiftAmount] R => wrapping_shl, Shl, FastWrappingShlKind::wrapping_shl_kind, OpsWrappingShlKind;
+    shr, shr_assign [R: ShiftAmount] R => wrapping_shr, Shr, FastWrappingShrKind::wrapping_shr_kind, OpsWrappingShrKind;
+}
+
+dispatch_policy! { SaturatingMath: SaturatingTag;
+    add, add_assign [] T => saturating_add, Add, FastSaturatingAddKind::saturating_add_kind, OpsSaturatingAddKind;
+    sub, sub_assign [] T => saturating_sub, Sub, FastSaturatingSubKind::saturating_sub_kind, OpsSaturatingSubKind;
+    mul, mul_assign [] T => saturating_mul, Mul, FastSaturatingMulKind::saturating_mul_kind, OpsSaturatingMulKind;
+    div, div_assign [] T => saturating_div, Div, FastSaturatingDivKind::saturating_div_kind, OpsSaturatingDivKind;
+    rem, rem_assign [] T => saturating_rem, Rem, FastSaturatingRemKind::saturating_rem_kind, OpsSaturatingRemKind;
+    shl, shl_assign [R: ShiftAmount] R => saturating_shl, Shl, FastSaturatingShlKind::saturating_shl_kind, OpsSaturatingShlKind;
+    shr, shr_assign [R: ShiftAmount] R => saturating_shr, Shr, FastSaturatingShrKind::saturating_shr_kind, OpsSaturatingShrKind;
+}
+
+// unary minus of policies, probed as `Probe<T, ()>` like `neg_kind`
+macro_rules! dispatch_policy_neg {
+    ($($policy:ident: $tag:ident, $func:ident, $fast_kind:ident::$kind:ident, $ops_kind:ident;)*) => {
+        $(
+            pub trait $fast_kind {
+                fn $kind(&self) -> $tag;
+            }
+
+            impl<T: $policy + Neg> $fast_kind for Probe<T, ()> {
+                #[inline(always)]
+                fn $kind(&self) -> $tag {
+                    $tag
+                }
+            }
+
+            pub trait $ops_kind {
+                fn $kind(&self) -> OpsTag;
+            }
+
+            impl<L, R> $ops_kind for &Probe<L, R> {
+                #[inline(always)]
+                fn $kind(&self) -> OpsTag {
+                    OpsTag
+                }
+            }
+
+            impl $tag {
+                #[inline(always)]
+                pub fn neg<T: $policy>(self, operand: T, _mode: Mode, _expr: &'static str) -> T {
+                    $policy::$func(operand)
+                }
+            }
+        )*
+    };
+}
+
+dispatch_policy_neg! {
+    WrappingMath: WrappingTag, wrapping_neg, FastWrappingNegKind::wrapping_neg_kind, OpsWrappingNegKind;
+    SaturatingMath: SaturatingTag, saturating_neg, FastSaturatingNegKind::saturating_neg_kind, OpsSaturatingNegKind;
+}
+
+// methods, indexing and casts keep the original expression for when these are false
+
+impl FastTag {
+    #[inline(always)]
+    pub fn is_fast(&self, _mode: Mode) -> bool {
+        true
+    }
+}
+
+impl OpsTag {
+    #[inline(always)]
+    pub fn is_fast(&self, _mode: Mode) -> bool {
+        false
+    }
+}
+
+impl OnlyTag {
+    #[inline(always)]
+    pub fn is_fast(&self, mode: Mode) -> bool {
+        mode.types.contains(self.0)
+    }
+}
+
+impl WrappingTag {
+    #[inline(always)]
+    pub fn is_fast(&self, _mode: Mode) -> bool {
+        true
+    }
+}
+
+impl SaturatingTag {
+    #[inline(always)]
+    pub fn is_fast(&self, _mode: Mode) -> bool {
+        true
+    }
+}
+
+macro_rules! dispatch_methods {
+    ($(
+        $name:ident($($arg:ident: $arg_ty:ty),*) => $bound:ident::$fast:ident($($flags:ident)?), $try_fast:ident,
+            $fast_kind:ident::$kind:ident, $ops_kind:ident, $only_fast_kind:ident::$only_kind:ident, $only_ops_kind:ident;
+    )*) => {
+        $(
+            pub trait $fast_kind {
+                fn $kind(&self) -> FastTag;
+            }
+
+            impl<T: $bound> $fast_kind for Probe<T, ()> {
+                #[inline(always)]
+                fn $kind(&self) -> FastTag {
+                    FastTag
+                }
+            }
+
+            pub trait $ops_kind {
+                fn $kind(&self) -> OpsTag;
+            }
+
+            impl<L, R> $ops_kind for &Probe<L, R> {
+                #[inline(always)]
+                fn $kind(&self) -> OpsTag {
+                    OpsTag
+                }
+            }
+
+            impl FastTag {
+                #[inline(always)]
+                #[track_caller]
+                pub fn $name<T: $bound + Copy>(self, receiver: &T, ($($arg,)*): ($($arg_ty,)*), mode: Mode, expr: &'static str) -> T {
+                    if mode.checks() {
+                        match (*receiver).$try_fast($($arg,)* $(mode.$flags)?) {
+                            Some(result) => result,
+                            None => mode.fail(stringify!($name), expr),
+                        }
+                    } else {
+                        (*receiver).$fast($($arg,)* $(mode.$flags)?)
+                    }
+                }
+            }
+
+            impl OpsTag {
+                // never called, generated code calls the original method instead
+                #[inline(always)]
+                pub fn $name<T, A>(self, _receiver: &T, _args: A, _mode: Mode, _expr: &'static str) -> ! {
+                    unreachable!()
+                }
+            }
+
+            pub trait $only_fast_kind {
+                fn $only_kind(&self) -> OnlyTag;
+            }
+
+            impl<T: $bound + Scalars> $only_fast_kind for Probe<T, ()> {
+                #[inline(always)]
+                fn $only_kind(&self) -> OnlyTag {
+                    OnlyTag(T::TYPES)
+                }
+            }
+
+            pub trait $only_ops_kind {
+                fn $only_kind(&self) -> OpsTag;
+            }
+
+            impl<L, R> $only_ops_kind for &Probe<L, R> {
+                #[inline(always)]
+                fn $only_kind(&self) -> OpsTag {
+                    OpsTag
+                }
+            }
+
+            impl OnlyTag {
+                // called only when `is_fast`
+                #[inline(always)]
+                #[track_caller]
+                pub fn $name<T: $bound + Copy>(self, receiver: &T, args: ($($arg_ty,)*), mode: Mode, expr: &'static str) -> T {
+                    FastTag.$name(receiver, args, mode, expr)
+                }
+            }
+        )*
+    };
+}
+
+// receiver is probed as `Probe<T, ()>`, arguments types follow from it
+dispatch_methods! {
+    abs() => FastAbs::fast_abs_with(float), try_fast_abs_with, FastAbsKind::abs_kind, OpsAbsKind,
+        FastOnlyAbsKind::only_abs_kind, OpsOnlyAbsKind;
+    div_euclid(rhs: T) => FastEuclid::fast_div_euclid_with(float), try_fast_div_euclid_with,
+        FastDivEuclidKind::div_euclid_kind, OpsDivEuclidKind,
+        FastOnlyDivEuclidKind::only_div_euclid_kind, OpsOnlyDivEuclidKind;
+    rem_euclid(rhs: T) => FastEuclid::fast_rem_euclid_with(float), try_fast_rem_euclid_with,
+        FastRemEuclidKind::rem_euclid_kind, OpsRemEuclidKind,
+        FastOnlyRemEuclidKind::only_rem_euclid_kind, OpsOnlyRemEuclidKind;
+    pow(exp: u32) => FastPow::fast_pow(), try_fast_pow, FastPowKind::pow_kind, OpsPowKind,
+        FastOnlyPowKind::only_pow_kind, OpsOnlyPowKind;
+    sqrt() => FastFloat::fast_sqrt_with(float), try_fast_sqrt_with, FastSqrtKind::sqrt_kind, OpsSqrtKind,
+        FastOnlySqrtKind::only_sqrt_kind, OpsOnlySqrtKind;
+    powi(n: i32) => FastFloat::fast_powi_with(float), try_fast_powi_with, FastPowiKind::powi_kind, OpsPowiKind,
+        FastOnlyPowiKind::only_powi_kind, OpsOnlyPowiKind;
+    mul_add(a: T, b: T) => FastFloat::fast_mul_add_with(float), try_fast_mul_add_with, FastMulAddKind::mul_add_kind, OpsMulAddKind,
+        FastOnlyMulAddKind::only_mul_add_kind, OpsOnlyMulAddKind;
+}
+
+// indexing, probed as `Probe<B, I>`, where B is the indexed container (or reference to it)
+
+/// Containers that can be indexed without bounds checks
+pub trait UncheckedIndex<I> {
+    type Output: ?Sized;
+
+    fn get(&self, index: I) -> Option<&Self::Output>;
+    unsafe fn get_unchecked(&self, index: I) -> &Self::Output;
+}
+
+pub trait UncheckedIndexMut<I>: UncheckedIndex<I> {
+    fn get_mut(&mut self, index: I) -> Option<&mut Self::Output>;
+    unsafe fn get_unchecked_mut(&mut self, index: I) -> &mut Self::Output;
+}
+
+// inherent methods are called through full paths, otherwise Vec would find these traits first and recurse
+macro_rules! impl_unchecked_index {
+    ($([$($generics:tt)*] $t:ty => $target:ty;)*) => {
+        $(
+            impl<$($generics)* I: SliceIndex<$target>> UncheckedIndex<I> for $t {
+                type Output = I::Output;
+
+                #[inline(always)]
+                fn get(&self, index: I) -> Option<&I::Output> {
+                    <$target>::get(self, index)
+                }
+
+                #[inline(always)]
+                unsafe fn get_unchecked(&self, index: I) -> &I::Output {
+                    unsafe { <$target>::get_unchecked(self, index) }
+                }
+            }
+
+            impl<$($generics)* I: SliceIndex<$target>> UncheckedIndexMut<I> for $t {
+                #[inline(always)]
+                fn get_mut(&mut self, index: I) -> Option<&mut I::Output> {
+                    <$target>::get_mut(self, index)
+                }
+
+                #[inline(always)]
+                unsafe fn get_unchecked_mut(&mut self, index: I) -> &mut I::Output {
+                    unsafe { <$target>::get_unchecked_mut(self, index) }
+                }
+            }
+        )*
+    };
+}
+
+impl_unchecked_index! {
+    [T,] [T] => [T];
+    [T, const N: usize,] [T; N] => [T];
+    [] str => str;
+}
+
+#[cfg(feature = "alloc")]
+impl_unchecked_index! {
+    [T,] alloc::vec::Vec<T> => [T];
+    [] alloc::string::String => str;
+}
+
+impl<B: UncheckedIndex<I> + ?Sized, I> UncheckedIndex<I> for &B {
+    type Output = B::Output;
+
+    #[inline(always)]
+    fn get(&self, index: I) -> Option<&B::Output> {
+        B::get(self, index)
+    }
+
+    #[inline(always)]
+    unsafe fn get_unchecked(&self, index: I) -> &B::Output {
+        unsafe { B::get_unchecked(self, index) }
+    }
+}
+
+impl<B: UncheckedIndex<I> + ?Sized, I> UncheckedIndex<I> for &mut B {
+    type Output = B::Output;
+
+    #[inline(always)]
+    fn get(&self, index: I) -> Option<&B::Output> {
+        B::get(self, index)
+    }
+
+    #[inline(always)]
+    unsafe fn get_unchecked(&self, index: I) -> &B::Output {
+        unsafe { B::get_unchecked(self, index) }
+    }
+}
+
+impl<B: UncheckedIndexMut<I> + ?Sized, I> UncheckedIndexMut<I> for &mut B {
+    #[inline(always)]
+    fn get_mut(&mut self, index: I) -> Option<&mut B::Output> {
+        B::get_mut(self, index)
+    }
+
+    #[inline(always)]
+    unsafe fn get_unchecked_mut(&mut self, index: I) -> &mut B::Output {
+        unsafe { B::get_unchecked_mut(self, index) }
+    }
+}
+
+/// Mutable borrow through method call autoref, so that `v[i] = x` works both for `mut v: Vec<T>` and `v: &mut Vec<T>`
+pub trait PlaceMut {
+    fn place_mut(&mut self) -> &mut Self;
+}
+
+impl<T: ?Sized> PlaceMut for T {
+    #[inline(always)]
+    fn place_mut(&mut self) -> &mut Self {
+        self
+    }
+}
+
+pub trait FastIndexKind {
+    fn index_kind(&self) -> FastTag;
+}
+
+impl<B: UncheckedIndex<I> + ?Sized, I> FastIndexKind for Probe<B, I> {
+    #[inline(always)]
+    fn index_kind(&self) -> FastTag {
+        FastTag
+    }
+}
+
+pub trait OpsIndexKind {
+    fn index_kind(&self) -> OpsTag;
+}
+
+impl<L: ?Sized, R: ?Sized> OpsIndexKind for &Probe<L, R> {
+    #[inline(always)]
+    fn index_kind(&self) -> OpsTag {
+        OpsTag
+    }
+}
+
+pub trait FastIndexMutKind {
+    fn index_mut_kind(&self) -> FastTag;
+}
+
+impl<B: UncheckedIndexMut<I> + ?Sized, I> FastIndexMutKind for Probe<B, I> {
+    #[inline(always)]
+    fn index_mut_kind(&self) -> FastTag {
+        FastTag
+    }
+}
+
+pub trait OpsIndexMutKind {
+    fn index_mut_kind(&self) -> OpsTag;
+}
+
+impl<L: ?Sized, R: ?Sized> OpsIndexMutKind for &Probe<L, R> {
+    #[inline(always)]
+    fn index_mut_kind(&self) -> OpsTag {
+        OpsTag
+    }
+}
+
+impl FastTag {
+    #[inline(always)]
+    #[track_caller]
+    pub fn index<'a, B: UncheckedIndex<I> + ?Sized, I>(self, base: &'a B, index: I, mode: Mode, expr: &'static str) -> &'a B::Output {
+        if mode.checks() {
+            match base.get(index) {
+                Some(element) => element,
+                None => mode.fail_index(expr),
+            }
+        } else {
+            unsafe { base.get_unchecked(index) }
+        }
+    }
+
+    #[inline(always)]
+    #[track_caller]
+    pub fn index_mut<'a, B: UncheckedIndexMut<I> + ?Sized, I>(
+        self,
+        base: &'a mut B,
+        index: I,
+        mode: Mode,
+        expr: &'static str,
+    ) -> &'a mut B::Output {
+        if mode.checks() {
+            match base.get_mut(index) {
+                Some(element) => element,
+                None => mode.fail_index(expr),
+            }
+        } else {
+            unsafe { base.get_unchecked_mut(index) }
+        }
+    }
+}
+
+impl OpsTag {
+    // never called, generated code indexes the original way instead
+    #[inline(always)]
+    pub fn index<B: ?Sized, I>(self, _base: &B, _index: I, _mode: Mode, _expr: &'static str) -> ! {
+        unreachable!()
+    }
+
+    #[inline(always)]
+    pub fn index_mut<B: ?Sized, I>(self, _base: &mut B, _index: I, _mode: Mode, _expr: &'static str) -> ! {
+        unreachable!()
+    }
+}
+
+// casts, probed as `Probe<T, U>` for `x as U`, and as `Probe<V, D>` for vek's `v.as_::<D>()`
+
+pub trait FastCastKind {
+    fn cast_kind(&self) -> FastTag;
+}
+
+impl<T: FastCast<U>, U> FastCastKind for Probe<T, U> {
+    #[inline(always)]
+    fn cast_kind(&self) -> FastTag {
+        FastTag
+    }
+}
+
+pub trait OpsCastKind {
+    fn cast_kind(&self) -> OpsTag;
+}
+
+impl<L: ?Sized, R: ?Sized> OpsCastKind for &Probe<L, R> {
+    #[inline(always)]
+    fn cast_kind(&self) -> OpsTag {
+        OpsTag
+    }
+}
+
+/// Vek types, `Vec3<S>::Of<D>` is `Vec3<D>`
+pub trait Elements {
+    type Of<D>;
+}
+
+pub trait FastAsKind {
+    fn as_kind(&self) -> FastTag;
+}
+
+impl<V: Elements + FastCast<V::Of<D>>, D> FastAsKind for Probe<V, D> {
+    #[inline(always)]
+    fn as_kind(&self) -> FastTag {
+        FastTag
+    }
+}
+
+pub trait OpsAsKind {
+    fn as_kind(&self) -> OpsTag;
+}
+
+impl<L: ?Sized, R: ?Sized> OpsAsKind for &Probe<L, R> {
+    #[inline(always)]
+    fn as_kind(&self) -> OpsTag {
+        OpsTag
+    }
+}
+
+impl FastTag {
+    #[inline(always)]
+    #[track_caller]
+    pub fn cast<T: FastCast<U>, U>(self, value: T, mode: Mode, expr: &'static str) -> U {
+        if mode.checks() {
+            match value.try_fast_cast() {
+                Some(result) => result,
+                None => mode.fail("cast", expr),
+            }
+        } else {
+            value.fast_cast()
+        }
+    }
+
+    #[inline(always)]
+    #[track_caller]
+    pub fn as_<D, V: Elements + FastCast<V::Of<D>> + Copy>(self, receiver: &V, _args: (), mode: Mode, expr: &'static str) -> V::Of<D> {
+        self.cast(*receiver, mode, expr)
+    }
+}
+
+impl OpsTag {
+    // never called, generated code casts the original way instead
+    #[inline(always)]
+    pub fn cast<T>(self, _value: T, _mode: Mode, _expr: &'static str) -> ! {
+        unreachable!()
+    }
+
+    #[inline(always)]
+    pub fn as_<D, V>(self, _receiver: &V, _args: (), _mode: Mode, _expr: &'static str) -> ! {
+        unreachable!()
+    }
+}
@@ -3,103 +3,1059 @@
 //! Originally i wanted to just pick functions based on type inside macro, but they dont have acces to type information.
 //! And traits just happen to do exactly whats needed.
 
-#![allow(internal_features)]
-#![feature(core_intrinsics)]
+#![no_std]
+// nightly gets real fast-math intrinsics, see build.rs
+#![cfg_attr(unsafe_math_nightly, allow(internal_features))]
+#![cfg_attr(unsafe_math_nightly, feature(core_intrinsics))]
 
-/// Helper trait to provide the fast-math operations for all integer and float types.
-pub trait UnsafeMath: Sized {
-    fn fast_add(self, rhs: Self) -> Self;
-    fn fast_sub(self, rhs: Self) -> Self;
-    fn fast_mul(self, rhs: Self) -> Self;
-    fn fast_div(self, rhs: Self) -> Self;
-    fn fast_rem(self, rhs: Self) -> Self;
-    fn fast_shl(self, rhs: u32) -> Self;
-    fn fast_shr(self, rhs: u32) -> Self;
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+#[doc(hidden)]
+pub mod __private;
+
+/// Fast `+`. Like `std::ops::Add<Rhs>`, right-hand side may be of another type:
+/// vek types implement it with their scalars, `v + 0.5` and `2.0 + v` both work.
+/// Primitives only have it for their own type, same as `std::ops::Add`: `u64 + u32` doesn't compile without the macro either.
+///
+/// `try_fast_*` versions (here and in other traits) return `None` exactly when corresponding `fast_*` would be UB.
+/// They are used by checked mode of the macro
+pub trait FastAdd<Rhs = Self>: Sized {
+    type Output;
+
+    fn fast_add(self, rhs: Rhs) -> Self::Output;
+
+    fn try_fast_add(self, rhs: Rhs) -> Option<Self::Output>;
+
+    /// Versions with only some of float fast-math flags, for `#[unsafe_math(float(...))]`, see [`FloatFlags`].
+    /// Only floats (and types made of them) need to override these, everything else has nothing to do with the flags
+    #[inline(always)]
+    fn fast_add_with(self, rhs: Rhs, _flags: FloatFlags) -> Self::Output {
+        self.fast_add(rhs)
+    }
+
+    #[inline(always)]
+    fn try_fast_add_with(self, rhs: Rhs, _flags: FloatFlags) -> Option<Self::Output> {
+        self.try_fast_add(rhs)
+    }
+}
+
+/// Fast `-`, see [`FastAdd`]
+pub trait FastSub<Rhs = Self>: Sized {
+    type Output;
+
+    fn fast_sub(self, rhs: Rhs) -> Self::Output;
+
+    fn try_fast_sub(self, rhs: Rhs) -> Option<Self::Output>;
+
+    #[inline(always)]
+    fn fast_sub_with(self, rhs: Rhs, _flags: FloatFlags) -> Self::Output {
+        self.fast_sub(rhs)
+    }
+
+    #[inline(always)]
+    fn try_fast_sub_with(self, rhs: Rhs, _flags: FloatFlags) -> Option<Self::Output> {
+        self.try_fast_sub(rhs)
+    }
+}
+
+/// Fast `*`, see [`FastAdd`]
+pub trait FastMul<Rhs = Self>: Sized {
+    type Output;
+
+    fn fast_mul(self, rhs: Rhs) -> Self::Output;
+
+    fn try_fast_mul(self, rhs: Rhs) -> Option<Self::Output>;
+
+    #[inline(always)]
+    fn fast_mul_with(self, rhs: Rhs, _flags: FloatFlags) -> Self::Output {
+        self.fast_mul(rhs)
+    }
+
+    #[inline(always)]
+    fn try_fast_mul_with(self, rhs: Rhs, _flags: FloatFlags) -> Option<Self::Output> {
+        self.try_fast_mul(rhs)
+    }
+}
+
+/// Fast `/`, see [`FastAdd`]
+pub trait FastDiv<Rhs = Self>: Sized {
+    type Output;
+
+    fn fast_div(self, rhs: Rhs) -> Self::Output;
+
+    fn try_fast_div(self, rhs: Rhs) -> Option<Self::Output>;
+
+    #[inline(always)]
+    fn fast_div_with(self, rhs: Rhs, _flags: FloatFlags) -> Self::Output {
+        self.fast_div(rhs)
+    }
+
+    #[inline(always)]
+    fn try_fast_div_with(self, rhs: Rhs, _flags: FloatFlags) -> Option<Self::Output> {
+        self.try_fast_div(rhs)
+    }
+}
+
+/// Fast `%`, see [`FastAdd`]
+pub trait FastRem<Rhs = Self>: Sized {
+    type Output;
+
+    fn fast_rem(self, rhs: Rhs) -> Self::Output;
+
+    fn try_fast_rem(self, rhs: Rhs) -> Option<Self::Output>;
+
+    #[inline(always)]
+    fn fast_rem_with(self, rhs: Rhs, _flags: FloatFlags) -> Self::Output {
+        self.fast_rem(rhs)
+    }
+
+    #[inline(always)]
+    fn try_fast_rem_with(self, rhs: Rhs, _flags: FloatFlags) -> Option<Self::Output> {
+        self.try_fast_rem(rhs)
+    }
+}
+
+/// Fast `<<`, for integers.
+///
+/// Shift amount can be of any integer type, same as for `std::ops::Shl`.
+/// Shifting by a negative amount or by at least the bit width of `Self` is UB.
+pub trait FastShl: Sized {
+    type Output;
+
+    fn fast_shl<R: ShiftAmount>(self, rhs: R) -> Self::Output;
+
+    fn try_fast_shl<R: ShiftAmount>(self, rhs: R) -> Option<Self::Output>;
+}
+
+/// Fast `>>`, see [`FastShl`]
+pub trait FastShr: Sized {
+    type Output;
+
+    fn fast_shr<R: ShiftAmount>(self, rhs: R) -> Self::Output;
+
+    fn try_fast_shr<R: ShiftAmount>(self, rhs: R) -> Option<Self::Output>;
+}
+
+/// Arithmetic that integers and floats have in common, with themselves.
+/// Implemented automatically for every type that has all five operators, handy as a bound in generic code.
+///
+/// Shifts are separate ([`FastShl`], [`FastShr`]), since floats don't have them.
+pub trait UnsafeMath:
+    FastAdd<Output = Self> + FastSub<Output = Self> + FastMul<Output = Self> + FastDiv<Output = Self> + FastRem<Output = Self>
+{
+}
+
+impl<T> UnsafeMath for T where
+    T: FastAdd<Output = T> + FastSub<Output = T> + FastMul<Output = T> + FastDiv<Output = T> + FastRem<Output = T>
+{
+}
+
+/// Right-hand side of `fast_shl`/`fast_shr`, implemented for all integer types
+pub trait ShiftAmount: Copy {
+    /// Shift amount as `u32`, `None` if it is negative or doesn't fit
+    fn shift_amount(self) -> Option<u32>;
+
+    /// Low bits of shift amount, for wrapping shifts (which mask it by bit width anyway)
+    fn shift_bits(self) -> u32;
+}
+
+/// Operators of `policy = wrapping`, for integers.
+///
+/// Same as `wrapping_*` methods of std, shifts take any integer amount like [`FastShl`].
+/// Division by zero still panics, as it does in std
+pub trait WrappingMath: Sized {
+    fn wrapping_add(self, rhs: Self) -> Self;
+    fn wrapping_sub(self, rhs: Self) -> Self;
+    fn wrapping_mul(self, rhs: Self) -> Self;
+    fn wrapping_div(self, rhs: Self) -> Self;
+    fn wrapping_rem(self, rhs: Self) -> Self;
+    fn wrapping_shl<R: ShiftAmount>(self, rhs: R) -> Self;
+    fn wrapping_shr<R: ShiftAmount>(self, rhs: R) -> Self;
+    fn wrapping_neg(self) -> Self;
+}
+
+/// Operators of `policy = saturating`, for integers.
+///
+/// Results that don't fit are clamped to `MIN`/`MAX`. For operations std has no saturating version of:
+/// `%` can't overflow except `MIN % -1`, which is 0; `<<` saturates when bits would be shifted out,
+/// `>>` by bit width or more gives 0 (-1 for negative values), negative shift amounts count as too large;
+/// negating unsigned integers gives 0.
+/// Division by zero still panics
+pub trait SaturatingMath: Sized {
+    fn saturating_add(self, rhs: Self) -> Self;
+    fn saturating_sub(self, rhs: Self) -> Self;
+    fn saturating_mul(self, rhs: Self) -> Self;
+    fn saturating_div(self, rhs: Self) -> Self;
+    fn saturating_rem(self, rhs: Self) -> Self;
+    fn saturating_shl<R: ShiftAmount>(self, rhs: R) -> Self;
+    fn saturating_shr<R: ShiftAmount>(self, rhs: R) -> Self;
+    fn saturating_neg(self) -> Self;
+}
+
+/// Fast negation, for signed integers and floats.
+///
+/// Not a part of UnsafeMath, since unsigned integers can't be negated at all
+pub trait FastNeg: Sized {
+    fn fast_neg(self) -> Self;
+
+    fn try_fast_neg(self) -> Option<Self>;
+
+    #[inline(always)]
+    fn fast_neg_with(self, _flags: FloatFlags) -> Self {
+        self.fast_neg()
+    }
+
+    #[inline(always)]
+    fn try_fast_neg_with(self, _flags: FloatFlags) -> Option<Self> {
+        self.try_fast_neg()
+    }
+}
+
+/// Fast `abs`, for signed integers and floats
+pub trait FastAbs: Sized {
+    fn fast_abs(self) -> Self;
+
+    fn try_fast_abs(self) -> Option<Self>;
+
+    #[inline(always)]
+    fn fast_abs_with(self, _flags: FloatFlags) -> Self {
+        self.fast_abs()
+    }
+
+    #[inline(always)]
+    fn try_fast_abs_with(self, _flags: FloatFlags) -> Option<Self> {
+        self.try_fast_abs()
+    }
+}
+
+/// Fast `div_euclid` and `rem_euclid`, for integers and floats
+pub trait FastEuclid: Sized {
+    fn fast_div_euclid(self, rhs: Self) -> Self;
+    fn fast_rem_euclid(self, rhs: Self) -> Self;
+
+    fn try_fast_div_euclid(self, rhs: Self) -> Option<Self>;
+    fn try_fast_rem_euclid(self, rhs: Self) -> Option<Self>;
+
+    #[inline(always)] fn fast_div_euclid_with(self, rhs: Self, _flags: FloatFlags) -> Self { self.fast_div_euclid(rhs) }
+    #[inline(always)] fn fast_rem_euclid_with(self, rhs: Self, _flags: FloatFlags) -> Self { self.fast_rem_euclid(rhs) }
+
+    #[inline(always)] fn try_fast_div_euclid_with(self, rhs: Self, _flags: FloatFlags) -> Option<Self> { self.try_fast_div_euclid(rhs) }
+    #[inline(always)] fn try_fast_rem_euclid_with(self, rhs: Self, _flags: FloatFlags) -> Option<Self> { self.try_fast_rem_euclid(rhs) }
+}
+
+/// Fast `pow`, for integers
+pub trait FastPow: Sized {
+    fn fast_pow(self, exp: u32) -> Self;
+
+    fn try_fast_pow(self, exp: u32) -> Option<Self>;
+}
+
+/// Fast `sqrt`, `powi` and `mul_add`, for floats
+pub trait FastFloat: Sized {
+    fn fast_sqrt(self) -> Self;
+    fn fast_powi(self, n: i32) -> Self;
+    /// Unlike `mul_add`, not necessarily fused: compiler is free to use fma instruction or separate mul and add,
+    /// whichever is faster (plain `mul_add` is a slow libm call on targets without fma)
+    fn fast_mul_add(self, a: Self, b: Self) -> Self;
+
+    fn try_fast_sqrt(self) -> Option<Self>;
+    fn try_fast_powi(self, n: i32) -> Option<Self>;
+    fn try_fast_mul_add(self, a: Self, b: Self) -> Option<Self>;
+
+    #[inline(always)] fn fast_sqrt_with(self, _flags: FloatFlags) -> Self { self.fast_sqrt() }
+    #[inline(always)] fn fast_powi_with(self, n: i32, _flags: FloatFlags) -> Self { self.fast_powi(n) }
+    /// Fused unless `flags` use `*_algebraic` intrinsics, see [`FloatFlags::algebraic`]
+    #[inline(always)] fn fast_mul_add_with(self, a: Self, b: Self, _flags: FloatFlags) -> Self { self.fast_mul_add(a, b) }
+
+    #[inline(always)] fn try_fast_sqrt_with(self, _flags: FloatFlags) -> Option<Self> { self.try_fast_sqrt() }
+    #[inline(always)] fn try_fast_powi_with(self, n: i32, _flags: FloatFlags) -> Option<Self> { self.try_fast_powi(n) }
+    #[inline(always)] fn try_fast_mul_add_with(self, a: Self, b: Self, _flags: FloatFlags) -> Option<Self> { self.try_fast_mul_add(a, b) }
+}
+
+/// Fast `as` casts.
+///
+/// Float to integer casts use `to_int_unchecked` (NaN, infinity and values out of target range are UB),
+/// narrowing integer casts of the same signedness assume the value fits into target type. Other numeric casts are just `as`
+pub trait FastCast<T>: Sized {
+    fn fast_cast(self) -> T;
+
+    fn try_fast_cast(self) -> Option<T>;
+}
+
+/// Set of LLVM fast-math flags for float operations, `#[unsafe_math(float(reassoc, contract))]`.
+///
+/// Macro uses [`FloatFlags::ALGEBRAIC`] by default: results may differ in last bits, but NaN and infinity are fine.
+/// `nnan` and `ninf` make them UB, so they have to be asked for explicitly.
+///
+/// Rust has intrinsics only for two sets: `*_fast` (everything) and `*_algebraic` (everything except `nnan` and `ninf`).
+/// Other sets map to one of them: `*_fast` with [`FloatFlags::ALL`], `*_algebraic` when `reassoc` or `contract` is set
+/// (so `float(reassoc)` also allows `contract`, `nsz`, `arcp` and `afn`), see [`FloatFlags::algebraic`].
+/// Without either of them operators stay exact IEEE ones, `nsz`, `arcp` and `afn` alone have no intrinsics.
+/// `nnan` and `ninf` are assumed separately, NaN or infinity is UB only when its flag is set.
+///
+/// Both kinds of intrinsics are nightly-only. On stable value-changing flags do nothing, `nnan` and `ninf` are still assumed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FloatFlags(u8);
+
+impl FloatFlags {
+    pub const NONE: FloatFlags = FloatFlags(0);
+    /// Allow reassociation, `(a + b) + c` to `a + (b + c)`. Lets reductions vectorize
+    pub const REASSOC: FloatFlags = FloatFlags(1 << 0);
+    /// Allow fusing multiply and add
+    pub const CONTRACT: FloatFlags = FloatFlags(1 << 1);
+    /// Assume there are no NaNs, in operands or results
+    pub const NNAN: FloatFlags = FloatFlags(1 << 2);
+    /// Assume there are no infinities, in operands or results
+    pub const NINF: FloatFlags = FloatFlags(1 << 3);
+    /// Ignore the sign of zero
+    pub const NSZ: FloatFlags = FloatFlags(1 << 4);
+    /// Allow `a / b` to become `a * (1 / b)`
+    pub const ARCP: FloatFlags = FloatFlags(1 << 5);
+    /// Allow approximate functions
+    pub const AFN: FloatFlags = FloatFlags(1 << 6);
+    /// What `fast_*` operations use
+    pub const ALL: FloatFlags = FloatFlags(0x7f);
+    /// Flags that change values of results, but never make anything UB. Default of the macro
+    pub const ALGEBRAIC: FloatFlags = FloatFlags(0x7f & !(1 << 2) & !(1 << 3));
+
+    pub const fn union(self, other: FloatFlags) -> FloatFlags {
+        FloatFlags(self.0 | other.0)
+    }
+
+    pub const fn contains(self, other: FloatFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Whether operations use `*_algebraic` intrinsics (on nightly), which is when `reassoc` or `contract` is set.
+    /// Unless all flags are set, then they use `*_fast` ones
+    pub const fn algebraic(self) -> bool {
+        self.0 & (FloatFlags::REASSOC.0 | FloatFlags::CONTRACT.0) != 0
+    }
+}
+
+/// Unwraps value that is known to be there, `None` is UB.
+/// Lets optimizer drop checks of `checked_*` functions
+#[inline(always)]
+unsafe fn assume_some<T>(value: Option<T>) -> T {
+    match value {
+        Some(value) => value,
+        None => unsafe { core::hint::unreachable_unchecked() },
+    }
+}
+
+/// Unchecked add, sub, mul or shift: intrinsic on nightly, `unchecked_*` method on stable
+macro_rules! unchecked_method {
+    ($unchecked:ident, $checked:ident($lhs:expr, $rhs:expr)) => {{
+        #[cfg(unsafe_math_nightly)]
+        let result = unsafe { core::intrinsics::$unchecked($lhs, $rhs) };
+        #[cfg(not(unsafe_math_nightly))]
+        let result = unsafe { $lhs.$unchecked($rhs) };
+        result
+    }};
+}
+
+/// Unchecked division and remainder, which only have intrinsics: those on nightly,
+/// `checked_*` with `None` assumed away on stable (optimizer drops the checks)
+macro_rules! unchecked_int {
+    ($unchecked:ident, $checked:ident($lhs:expr, $rhs:expr)) => {{
+        #[cfg(unsafe_math_nightly)]
+        let result = unsafe { core::intrinsics::$unchecked($lhs, $rhs) };
+        #[cfg(not(unsafe_math_nightly))]
+        let result = unsafe { assume_some($lhs.$checked($rhs)) };
+        result
+    }};
 }
 
 macro_rules! impl_fast_math_for_int {
         ($($t:ty),*) => {
             $(
-                impl UnsafeMath for $t {
-                    #[inline(always)] fn fast_add(self, rhs: Self) -> Self { unsafe { std::intrinsics::unchecked_add(self, rhs) } }
-                    #[inline(always)] fn fast_sub(self, rhs: Self) -> Self { unsafe { std::intrinsics::unchecked_sub(self, rhs) } }
-                    #[inline(always)] fn fast_mul(self, rhs: Self) -> Self { unsafe { std::intrinsics::unchecked_mul(self, rhs) } }
-                    #[inline(always)] fn fast_div(self, rhs: Self) -> Self { unsafe { std::intrinsics::unchecked_div(self, rhs) } }
-                    #[inline(always)] fn fast_rem(self, rhs: Self) -> Self { unsafe { std::intrinsics::unchecked_rem(self, rhs) } }
-                    #[inline(always)] fn fast_shl(self, rhs: u32) -> Self { unsafe { std::intrinsics::unchecked_shl(self, rhs) } }
-                    #[inline(always)] fn fast_shr(self, rhs: u32) -> Self { unsafe { std::intrinsics::unchecked_shr(self, rhs) } }
+                impl_fast_op_for_int!($t, FastAdd::fast_add, try_fast_add, fast_add_with, try_fast_add_with => unchecked_method!(unchecked_add, checked_add));
+                impl_fast_op_for_int!($t, FastSub::fast_sub, try_fast_sub, fast_sub_with, try_fast_sub_with => unchecked_method!(unchecked_sub, checked_sub));
+                impl_fast_op_for_int!($t, FastMul::fast_mul, try_fast_mul, fast_mul_with, try_fast_mul_with => unchecked_method!(unchecked_mul, checked_mul));
+                impl_fast_op_for_int!($t, FastDiv::fast_div, try_fast_div, fast_div_with, try_fast_div_with => unchecked_int!(unchecked_div, checked_div));
+                impl_fast_op_for_int!($t, FastRem::fast_rem, try_fast_rem, fast_rem_with, try_fast_rem_with => unchecked_int!(unchecked_rem, checked_rem));
+
+                impl FastShl for $t {
+                    type Output = $t;
+
+                    #[inline(always)] fn fast_shl<R: ShiftAmount>(self, rhs: R) -> Self { unchecked_method!(unchecked_shl, checked_shl(self, assume_some(rhs.shift_amount()))) }
+
+                    #[inline(always)] fn try_fast_shl<R: ShiftAmount>(self, rhs: R) -> Option<Self> { self.checked_shl(rhs.shift_amount()?) }
+                }
+
+                impl FastShr for $t {
+                    type Output = $t;
+
+                    #[inline(always)] fn fast_shr<R: ShiftAmount>(self, rhs: R) -> Self { unchecked_method!(unchecked_shr, checked_shr(self, assume_some(rhs.shift_amount()))) }
+
+                    #[inline(always)] fn try_fast_shr<R: ShiftAmount>(self, rhs: R) -> Option<Self> { self.checked_shr(rhs.shift_amount()?) }
+                }
+
+                impl_fast_op_for_refs!(shift <> $t where []; FastShl::fast_shl, try_fast_shl);
+                impl_fast_op_for_refs!(shift <> $t where []; FastShr::fast_shr, try_fast_shr);
+
+                impl ShiftAmount for $t {
+                    #[inline(always)] fn shift_amount(self) -> Option<u32> { u32::try_from(self).ok() }
+
+                    #[inline(always)] fn shift_bits(self) -> u32 { self as u32 }
                 }
             )*
         };
     }
+macro_rules! impl_fast_op_for_int {
+    ($t:ty, $trait_:ident::$fast:ident, $try_fast:ident, $fast_with:ident, $try_fast_with:ident => $unchecked_op:ident!($unchecked:ident, $checked:ident)) => {
+        impl $trait_ for $t {
+            type Output = $t;
+
+            #[inline(always)] fn $fast(self, rhs: Self) -> Self { $unchecked_op!($unchecked, $checked(self, rhs)) }
+
+            #[inline(always)] fn $try_fast(self, rhs: Self) -> Option<Self> { self.$checked(rhs) }
+        }
+
+        impl_fast_op_for_refs!(<> $t, $t where []; $trait_::$fast, $try_fast, $fast_with, $try_fast_with);
+    };
+}
+
+impl<T: ShiftAmount> ShiftAmount for &T {
+    #[inline(always)]
+    fn shift_amount(self) -> Option<u32> {
+        (*self).shift_amount()
+    }
+
+    #[inline(always)]
+    fn shift_bits(self) -> u32 {
+        (*self).shift_bits()
+    }
+}
 macro_rules! impl_fast_math_for_float {
         ($($t:ty),*) => {
             $(
-                impl UnsafeMath for $t {
-                    #[inline(always)] fn fast_add(self, rhs: Self) -> Self { unsafe { core::intrinsics::fadd_fast(self, rhs) } }
-                    #[inline(always)] fn fast_sub(self, rhs: Self) -> Self { unsafe { core::intrinsics::fsub_fast(self, rhs) } }
-                    #[inline(always)] fn fast_mul(self, rhs: Self) -> Self { unsafe { core::intrinsics::fmul_fast(self, rhs) } }
-                    #[inline(always)] fn fast_div(self, rhs: Self) -> Self { unsafe { core::intrinsics::fdiv_fast(self, rhs) } }
-                    #[inline(always)] fn fast_rem(self, rhs: Self) -> Self { unsafe { core::intrinsics::frem_fast(self, rhs) } }
-                    #[inline(always)] fn fast_shl(self, _rhs: u32) -> Self { unsafe { std::hint::unreachable_unchecked() } }
-                    #[inline(always)] fn fast_shr(self, _rhs: u32) -> Self { unsafe { std::hint::unreachable_unchecked() } }
-                }
+                impl_fast_op_for_float!($t, FastAdd::fast_add, try_fast_add, fast_add_with, try_fast_add_with => fadd_fast, fadd_algebraic, +);
+                impl_fast_op_for_float!($t, FastSub::fast_sub, try_fast_sub, fast_sub_with, try_fast_sub_with => fsub_fast, fsub_algebraic, -);
+                impl_fast_op_for_float!($t, FastMul::fast_mul, try_fast_mul, fast_mul_with, try_fast_mul_with => fmul_fast, fmul_algebraic, *);
+                impl_fast_op_for_float!($t, FastDiv::fast_div, try_fast_div, fast_div_with, try_fast_div_with => fdiv_fast, fdiv_algebraic, /);
+                impl_fast_op_for_float!($t, FastRem::fast_rem, try_fast_rem, fast_rem_with, try_fast_rem_with => frem_fast, frem_algebraic, %);
             )*
         };
     }
+macro_rules! impl_fast_op_for_float {
+    ($t:ty, $trait_:ident::$fast:ident, $try_fast:ident, $fast_with:ident, $try_fast_with:ident => $intrinsic:ident, $algebraic:ident, $op:tt) => {
+        impl $trait_ for $t {
+            type Output = $t;
+
+            #[inline(always)]
+            fn $fast(self, rhs: Self) -> Self {
+                #[cfg(unsafe_math_nightly)]
+                let result = unsafe { core::intrinsics::$intrinsic(self, rhs) };
+                #[cfg(not(unsafe_math_nightly))]
+                let result = unsafe { assume_allowed(FloatFlags::ALL, self, rhs, self $op rhs) };
+                result
+            }
+
+            // fast-math float ops are UB when any operand or the result is NaN or infinite
+            #[inline(always)] fn $try_fast(self, rhs: Self) -> Option<Self> { finite(self, rhs, self $op rhs) }
+
+            #[inline(always)]
+            fn $fast_with(self, rhs: Self, flags: FloatFlags) -> Self {
+                if flags == FloatFlags::ALL {
+                    return self.$fast(rhs);
+                }
+                let result = match flags.algebraic() {
+                    #[cfg(unsafe_math_nightly)]
+                    true => core::intrinsics::$algebraic(self, rhs),
+                    _ => self $op rhs,
+                };
+                unsafe { assume_allowed(flags, self, rhs, result) }
+            }
+
+            #[inline(always)] fn $try_fast_with(self, rhs: Self, flags: FloatFlags) -> Option<Self> { allowed(flags, self, rhs, self $op rhs) }
+        }
+
+        impl_fast_op_for_refs!(<> $t, $t where []; $trait_::$fast, $try_fast, $fast_with, $try_fast_with);
+    };
+}
+
+/// Returns `result` if it and both operands are finite
+#[inline(always)]
+fn finite<F: Into<f64> + Copy>(lhs: F, rhs: F, result: F) -> Option<F> {
+    allowed(FloatFlags::ALL, lhs, rhs, result)
+}
+
+/// Returns `result` unless `flags` assume away NaN or infinity, and it or one of operands is one
+#[inline(always)]
+fn allowed<F: Into<f64> + Copy>(flags: FloatFlags, lhs: F, rhs: F, result: F) -> Option<F> {
+    let nan = flags.contains(FloatFlags::NNAN);
+    let inf = flags.contains(FloatFlags::NINF);
+    [lhs, rhs, result]
+        .into_iter()
+        .map(Into::into)
+        .all(|f: f64| !(nan && f.is_nan() || inf && f.is_infinite()))
+        .then_some(result)
+}
+
+/// `result`, with what `flags` assume about it and operands told to optimizer
+#[inline(always)]
+unsafe fn assume_allowed<F: Into<f64> + Copy>(flags: FloatFlags, lhs: F, rhs: F, result: F) -> F {
+    unsafe { assume_some(allowed(flags, lhs, rhs, result)) }
+}
+
+// `&a + &b`, `a * &b`... delegate to owned `$trait_<$rhs> for $lhs`, same as std does for primitives
+macro_rules! impl_fast_op_for_refs {
+    (<$($g:ident),*> $lhs:ty, $rhs:ty where [$($bound:tt)*]; $trait_:ident::$($fns:ident),+) => {
+        impl<'a, $($g),*> $trait_<&'a $rhs> for $lhs where $($bound)* {
+            type Output = <$lhs as $trait_<$rhs>>::Output;
+            impl_fast_op_for_refs!(@fns $($fns),+; &'a $rhs, [], [*]);
+        }
+
+        impl<'a, $($g),*> $trait_<$rhs> for &'a $lhs where $($bound)* {
+            type Output = <$lhs as $trait_<$rhs>>::Output;
+            impl_fast_op_for_refs!(@fns $($fns),+; $rhs, [*], []);
+        }
+
+        impl<'a, 'b, $($g),*> $trait_<&'b $rhs> for &'a $lhs where $($bound)* {
+            type Output = <$lhs as $trait_<$rhs>>::Output;
+            impl_fast_op_for_refs!(@fns $($fns),+; &'b $rhs, [*], [*]);
+        }
+    };
+    // shifts take any amount, `&T` amounts are covered by `ShiftAmount`
+    (shift <$($g:ident),*> $lhs:ty where [$($bound:tt)*]; $trait_:ident::$fast:ident, $try_fast:ident) => {
+        impl<'a, $($g),*> $trait_ for &'a $lhs where $($bound)* {
+            type Output = <$lhs as $trait_>::Output;
+
+            #[inline(always)] fn $fast<R: ShiftAmount>(self, rhs: R) -> Self::Output { (*self).$fast(rhs) }
+
+            #[inline(always)] fn $try_fast<R: ShiftAmount>(self, rhs: R) -> Option<Self::Output> { (*self).$try_fast(rhs) }
+        }
+    };
+    (@fns $fast:ident, $try_fast:ident, $fast_with:ident, $try_fast_with:ident; $rhs:ty, [$($lhs_deref:tt)?], [$($rhs_deref:tt)?]) => {
+        #[inline(always)] fn $fast(self, rhs: $rhs) -> Self::Output { ($($lhs_deref)? self).$fast($($rhs_deref)? rhs) }
+
+        #[inline(always)] fn $try_fast(self, rhs: $rhs) -> Option<Self::Output> { ($($lhs_deref)? self).$try_fast($($rhs_deref)? rhs) }
+
+        #[inline(always)]
+        fn $fast_with(self, rhs: $rhs, flags: FloatFlags) -> Self::Output {
+            ($($lhs_deref)? self).$fast_with($($rhs_deref)? rhs, flags)
+        }
+
+        #[inline(always)]
+        fn $try_fast_with(self, rhs: $rhs, flags: FloatFlags) -> Option<Self::Output> {
+            ($($lhs_deref)? self).$try_fast_with($($rhs_deref)? rhs, flags)
+        }
+    };
+}
+
+// inherent methods are called through `<$t>::`, otherwise these would call themselves
+macro_rules! impl_policies_for_int {
+    (signed: $($t:ty),*) => {
+        $( impl_policies_for_int!(@impl $t, |x: $t| if x < 0 { <$t>::MIN } else { <$t>::MAX }, <$t>::saturating_neg); )*
+    };
+    (unsigned: $($t:ty),*) => {
+        $( impl_policies_for_int!(@impl $t, |_: $t| <$t>::MAX, |_: $t| 0); )*
+    };
+    (@impl $t:ty, $saturated:expr, $saturating_neg:expr) => {
+        impl WrappingMath for $t {
+            #[inline(always)] fn wrapping_add(self, rhs: Self) -> Self { <$t>::wrapping_add(self, rhs) }
+            #[inline(always)] fn wrapping_sub(self, rhs: Self) -> Self { <$t>::wrapping_sub(self, rhs) }
+            #[inline(always)] fn wrapping_mul(self, rhs: Self) -> Self { <$t>::wrapping_mul(self, rhs) }
+            #[inline(always)] fn wrapping_div(self, rhs: Self) -> Self { <$t>::wrapping_div(self, rhs) }
+            #[inline(always)] fn wrapping_rem(self, rhs: Self) -> Self { <$t>::wrapping_rem(self, rhs) }
+            #[inline(always)] fn wrapping_shl<R: ShiftAmount>(self, rhs: R) -> Self { <$t>::wrapping_shl(self, rhs.shift_bits()) }
+            #[inline(always)] fn wrapping_shr<R: ShiftAmount>(self, rhs: R) -> Self { <$t>::wrapping_shr(self, rhs.shift_bits()) }
+            #[inline(always)] fn wrapping_neg(self) -> Self { <$t>::wrapping_neg(self) }
+        }
+
+        impl SaturatingMath for $t {
+            #[inline(always)] fn saturating_add(self, rhs: Self) -> Self { <$t>::saturating_add(self, rhs) }
+            #[inline(always)] fn saturating_sub(self, rhs: Self) -> Self { <$t>::saturating_sub(self, rhs) }
+            #[inline(always)] fn saturating_mul(self, rhs: Self) -> Self { <$t>::saturating_mul(self, rhs) }
+            #[inline(always)] fn saturating_div(self, rhs: Self) -> Self { <$t>::saturating_div(self, rhs) }
+            #[inline(always)] fn saturating_rem(self, rhs: Self) -> Self { <$t>::wrapping_rem(self, rhs) }
+
+            // saturates if shifting back doesn't give the original value
+            #[inline(always)]
+            fn saturating_shl<R: ShiftAmount>(self, rhs: R) -> Self {
+                match rhs.shift_amount() {
+                    _ if self == 0 => 0,
+                    Some(amount) if amount < <$t>::BITS && (self << amount) >> amount == self => self << amount,
+                    _ => ($saturated)(self),
+                }
+            }
+
+            #[inline(always)]
+            fn saturating_shr<R: ShiftAmount>(self, rhs: R) -> Self {
+                match rhs.shift_amount() {
+                    Some(amount) if amount < <$t>::BITS => self >> amount,
+                    // all bits shifted out, only the sign is left
+                    _ => self >> (<$t>::BITS - 1) >> 1,
+                }
+            }
+
+            #[inline(always)] fn saturating_neg(self) -> Self { ($saturating_neg)(self) }
+        }
+    };
+}
+
+macro_rules! impl_fast_neg_for_int {
+    ($($t:ty),*) => {
+        $(
+            impl FastNeg for $t {
+                #[inline(always)] fn fast_neg(self) -> Self { unchecked_method!(unchecked_sub, checked_sub(0 as $t, self)) }
+
+                #[inline(always)] fn try_fast_neg(self) -> Option<Self> { self.checked_neg() }
+            }
+        )*
+    };
+}
+macro_rules! impl_fast_neg_for_float {
+    ($($t:ty),*) => {
+        $(
+            impl FastNeg for $t {
+                // -0.0 - x is exactly -x, including zeros, but carries fast-math flags
+                #[inline(always)]
+                fn fast_neg(self) -> Self {
+                    #[cfg(unsafe_math_nightly)]
+                    let result = unsafe { core::intrinsics::fsub_fast(-0.0, self) };
+                    #[cfg(not(unsafe_math_nightly))]
+                    let result = unsafe { assume_allowed(FloatFlags::ALL, self, self, -self) };
+                    result
+                }
+
+                #[inline(always)] fn try_fast_neg(self) -> Option<Self> { finite(self, self, -self) }
+
+                #[inline(always)]
+                fn fast_neg_with(self, flags: FloatFlags) -> Self {
+                    if flags == FloatFlags::ALL {
+                        return self.fast_neg();
+                    }
+                    unsafe { assume_allowed(flags, self, self, -self) }
+                }
+
+                #[inline(always)] fn try_fast_neg_with(self, flags: FloatFlags) -> Option<Self> { allowed(flags, self, self, -self) }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_fast_methods_for_int {
+    ($($t:ty),*) => {
+        $(
+            impl FastEuclid for $t {
+                #[inline(always)] fn fast_div_euclid(self, rhs: Self) -> Self { unsafe { assume_some(self.checked_div_euclid(rhs)) } }
+                #[inline(always)] fn fast_rem_euclid(self, rhs: Self) -> Self { unsafe { assume_some(self.checked_rem_euclid(rhs)) } }
+
+                #[inline(always)] fn try_fast_div_euclid(self, rhs: Self) -> Option<Self> { self.checked_div_euclid(rhs) }
+                #[inline(always)] fn try_fast_rem_euclid(self, rhs: Self) -> Option<Self> { self.checked_rem_euclid(rhs) }
+            }
+
+            impl FastPow for $t {
+                #[inline(always)] fn fast_pow(self, exp: u32) -> Self { unsafe { assume_some(self.checked_pow(exp)) } }
+
+                #[inline(always)] fn try_fast_pow(self, exp: u32) -> Option<Self> { self.checked_pow(exp) }
+            }
+        )*
+    };
+}
+macro_rules! impl_fast_abs_for_int {
+    ($($t:ty),*) => {
+        $(
+            impl FastAbs for $t {
+                #[inline(always)] fn fast_abs(self) -> Self { unsafe { assume_some(self.checked_abs()) } }
+
+                #[inline(always)] fn try_fast_abs(self) -> Option<Self> { self.checked_abs() }
+            }
+        )*
+    };
+}
+macro_rules! impl_fast_methods_for_float {
+    ($($t:ty),*) => {
+        $(
+            impl FastAbs for $t {
+                // nothing to speed up, but keeps checked mode consistent with other float ops
+                #[inline(always)] fn fast_abs(self) -> Self { self.abs() }
+
+                #[inline(always)] fn try_fast_abs(self) -> Option<Self> { finite(self, self, self.abs()) }
+
+                #[inline(always)] fn fast_abs_with(self, flags: FloatFlags) -> Self { unsafe { assume_allowed(flags, self, self, self.abs()) } }
+
+                #[inline(always)] fn try_fast_abs_with(self, flags: FloatFlags) -> Option<Self> { allowed(flags, self, self, self.abs()) }
+            }
+
+            // `trunc`, `sqrt`, `powi`, `mul_add` and euclid methods of floats are not in core
+            #[cfg(feature = "std")]
+            impl FastEuclid for $t {
+                #[inline(always)] fn fast_div_euclid(self, rhs: Self) -> Self { self.fast_div_euclid_with(rhs, FloatFlags::ALL) }
+                #[inline(always)] fn fast_rem_euclid(self, rhs: Self) -> Self { self.fast_rem_euclid_with(rhs, FloatFlags::ALL) }
+
+                #[inline(always)] fn try_fast_div_euclid(self, rhs: Self) -> Option<Self> { finite(self, rhs, self.div_euclid(rhs)) }
+                #[inline(always)] fn try_fast_rem_euclid(self, rhs: Self) -> Option<Self> { finite(self, rhs, self.rem_euclid(rhs)) }
+
+                // same as std versions, but with fast-math ops
+                #[inline(always)]
+                fn fast_div_euclid_with(self, rhs: Self, flags: FloatFlags) -> Self {
+                    let q = self.fast_div_with(rhs, flags).trunc();
+                    if self.fast_rem_with(rhs, flags) < 0.0 {
+                        return if rhs > 0.0 { q.fast_sub_with(1.0, flags) } else { q.fast_add_with(1.0, flags) };
+                    }
+                    q
+                }
+                #[inline(always)]
+                fn fast_rem_euclid_with(self, rhs: Self, flags: FloatFlags) -> Self {
+                    let r = self.fast_rem_with(rhs, flags);
+                    if r < 0.0 { r.fast_add_with(rhs.abs(), flags) } else { r }
+                }
+
+                #[inline(always)] fn try_fast_div_euclid_with(self, rhs: Self, flags: FloatFlags) -> Option<Self> { allowed(flags, self, rhs, self.div_euclid(rhs)) }
+                #[inline(always)] fn try_fast_rem_euclid_with(self, rhs: Self, flags: FloatFlags) -> Option<Self> { allowed(flags, self, rhs, self.rem_euclid(rhs)) }
+            }
+
+            #[cfg(feature = "std")]
+            impl FastFloat for $t {
+                #[inline(always)] fn fast_sqrt(self) -> Self { self.fast_sqrt_with(FloatFlags::ALL) }
+                #[inline(always)] fn fast_powi(self, n: i32) -> Self { self.fast_powi_with(n, FloatFlags::ALL) }
+                #[inline(always)] fn fast_mul_add(self, a: Self, b: Self) -> Self { self.fast_mul_add_with(a, b, FloatFlags::ALL) }
+
+                #[inline(always)] fn try_fast_sqrt(self) -> Option<Self> { finite(self, self, self.sqrt()) }
+                #[inline(always)] fn try_fast_powi(self, n: i32) -> Option<Self> { finite(self, self, self.powi(n)) }
+                #[inline(always)]
+                fn try_fast_mul_add(self, a: Self, b: Self) -> Option<Self> {
+                    finite(self, a, self * a)?;
+                    finite(self * a, b, self * a + b)
+                }
+
+                // negative input (the only way to get NaN from non-NaN one) is assumed away with nnan
+                #[inline(always)]
+                fn fast_sqrt_with(self, flags: FloatFlags) -> Self {
+                    if flags.contains(FloatFlags::NNAN) {
+                        unsafe { core::hint::assert_unchecked(self >= 0.0) };
+                    }
+                    unsafe { assume_allowed(flags, self, self, self.sqrt()) }
+                }
+                #[inline(always)] fn fast_powi_with(self, n: i32, flags: FloatFlags) -> Self { unsafe { assume_allowed(flags, self, self, self.powi(n)) } }
+                #[inline(always)]
+                fn fast_mul_add_with(self, a: Self, b: Self, flags: FloatFlags) -> Self {
+                    if flags.algebraic() {
+                        return self.fast_mul_with(a, flags).fast_add_with(b, flags);
+                    }
+                    unsafe { assume_allowed(flags, self, a, assume_allowed(flags, b, b, self.mul_add(a, b))) }
+                }
+
+                #[inline(always)] fn try_fast_sqrt_with(self, flags: FloatFlags) -> Option<Self> { allowed(flags, self, self, self.sqrt()) }
+                #[inline(always)] fn try_fast_powi_with(self, n: i32, flags: FloatFlags) -> Option<Self> { allowed(flags, self, self, self.powi(n)) }
+                #[inline(always)]
+                fn try_fast_mul_add_with(self, a: Self, b: Self, flags: FloatFlags) -> Option<Self> {
+                    if flags.algebraic() {
+                        allowed(flags, self, a, self * a)?;
+                        return allowed(flags, self * a, b, self * a + b);
+                    }
+                    allowed(flags, self, a, allowed(flags, b, b, self.mul_add(a, b))?)
+                }
+            }
+        )*
+    };
+}
+
+/// Integer casts that assume the value fits: narrowing ones that keep signedness.
+/// Sign-changing casts (`-1i32 as u8`) often reinterpret bits on purpose, so these stay plain `as`
+const fn assumes_fit<F, T>(from_signed: bool, to_signed: bool) -> bool {
+    size_of::<F>() > size_of::<T>() && from_signed == to_signed
+}
+
+macro_rules! impl_fast_cast {
+    ($kind:ident: $($from:ty),* => $to:tt) => {
+        $( impl_fast_cast!(@$kind $from => $to); )*
+    };
+    (@int $from:ty => [$($to:ty),*]) => {
+        $(
+            impl FastCast<$to> for $from {
+                #[inline(always)]
+                fn fast_cast(self) -> $to {
+                    if assumes_fit::<$from, $to>(<$from>::MIN != 0, <$to>::MIN != 0) {
+                        unsafe { core::hint::assert_unchecked(<$to>::try_from(self).is_ok()) };
+                    }
+                    self as $to
+                }
+
+                #[inline(always)]
+                fn try_fast_cast(self) -> Option<$to> {
+                    if assumes_fit::<$from, $to>(<$from>::MIN != 0, <$to>::MIN != 0) {
+                        <$to>::try_from(self).ok()
+                    } else {
+                        Some(self as $to)
+                    }
+                }
+            }
+        )*
+    };
+    (@float_to_int $from:ty => [$($to:ty),*]) => {
+        $(
+            impl FastCast<$to> for $from {
+                #[inline(always)] fn fast_cast(self) -> $to { unsafe { self.to_int_unchecked() } }
+
+                // truncated value must fit: self is in (MIN - 1, MAX + 1). MAX + 1 is a power of two, so exact in f64.
+                // MIN - 1 rounds to MIN for wide types, but then there are no floats between them anyway
+                // (no `trunc` here, it is not in core)
+                #[inline(always)]
+                fn try_fast_cast(self) -> Option<$to> {
+                    let value = self as f64;
+                    let above_min = value > <$to>::MIN as f64 - 1.0 || value >= <$to>::MIN as f64;
+                    (above_min && value < <$to>::MAX as f64 + 1.0).then_some(self as $to)
+                }
+            }
+        )*
+    };
+    (@lossy $from:ty => [$($to:ty),*]) => {
+        $(
+            impl FastCast<$to> for $from {
+                #[inline(always)] fn fast_cast(self) -> $to { self as $to }
+
+                #[inline(always)] fn try_fast_cast(self) -> Option<$to> { Some(self as $to) }
+            }
+        )*
+    };
+}
 
 impl_fast_math_for_int!(i8, u8, i16, u16, i32, u32, i64, u64, i128, u128, isize, usize);
 impl_fast_math_for_float!(f32, f64);
+impl_fast_cast!(int: i8, u8, i16, u16, i32, u32, i64, u64, i128, u128, isize, usize
+    => [i8, u8, i16, u16, i32, u32, i64, u64, i128, u128, isize, usize]);
+impl_fast_cast!(float_to_int: f32, f64 => [i8, u8, i16, u16, i32, u32, i64, u64, i128, u128, isize, usize]);
+impl_fast_cast!(lossy: i8, u8, i16, u16, i32, u32, i64, u64, i128, u128, isize, usize, f32, f64 => [f32, f64]);
+impl_policies_for_int!(signed: i8, i16, i32, i64, i128, isize);
+impl_policies_for_int!(unsigned: u8, u16, u32, u64, u128, usize);
+impl_fast_neg_for_int!(i8, i16, i32, i64, i128, isize);
+impl_fast_neg_for_float!(f32, f64);
+impl_fast_methods_for_int!(i8, u8, i16, u16, i32, u32, i64, u64, i128, u128, isize, usize);
+impl_fast_abs_for_int!(i8, i16, i32, i64, i128, isize);
+impl_fast_methods_for_float!(f32, f64);
 
+#[cfg(feature = "std")]
 macro_rules! impl_fast_math_for_vek {
-    ($t:ident { $($field:ident),+ }) => {
-        impl<S> UnsafeMath for $t<S>
+    ($t:ident { $($field:ident),+ } for $($s:ty),*) => {
+        // same as `as_::<D>()` of vek, element-wise
+        impl<S, D> FastCast<$t<D>> for $t<S>
         where
-            S: Copy + UnsafeMath,
+            S: FastCast<D>,
         {
             #[inline(always)]
-            fn fast_add(self, rhs: Self) -> Self {
-                Self { $( $field: self.$field.fast_add(rhs.$field) ),+ }
+            fn fast_cast(self) -> $t<D> {
+                $t { $( $field: self.$field.fast_cast() ),+ }
             }
+
             #[inline(always)]
-            fn fast_sub(self, rhs: Self) -> Self {
-                Self { $( $field: self.$field.fast_sub(rhs.$field) ),+ }
+            fn try_fast_cast(self) -> Option<$t<D>> {
+                Some($t { $( $field: self.$field.try_fast_cast()? ),+ })
             }
+        }
+
+        impl<S: __private::Scalars> __private::Scalars for $t<S> {
+            const TYPES: __private::Types = S::TYPES;
+        }
+
+        impl<S> __private::Elements for $t<S> {
+            type Of<D> = $t<D>;
+        }
+
+        impl_fast_op_for_vek!($t { $($field),+ }; FastAdd::fast_add, try_fast_add, fast_add_with, try_fast_add_with);
+        impl_fast_op_for_vek!($t { $($field),+ }; FastSub::fast_sub, try_fast_sub, fast_sub_with, try_fast_sub_with);
+        impl_fast_op_for_vek!($t { $($field),+ }; FastMul::fast_mul, try_fast_mul, fast_mul_with, try_fast_mul_with);
+        impl_fast_op_for_vek!($t { $($field),+ }; FastDiv::fast_div, try_fast_div, fast_div_with, try_fast_div_with);
+        impl_fast_op_for_vek!($t { $($field),+ }; FastRem::fast_rem, try_fast_rem, fast_rem_with, try_fast_rem_with);
+
+        // `2.0 * v`. vek has it only for `+` and `*`
+        impl_fast_op_for_vek!(@scalar_lhs $t; FastAdd::fast_add, try_fast_add, fast_add_with, try_fast_add_with; $($s),*);
+        impl_fast_op_for_vek!(@scalar_lhs $t; FastMul::fast_mul, try_fast_mul, fast_mul_with, try_fast_mul_with; $($s),*);
+
+        impl<S> FastShl for $t<S>
+        where
+            S: Copy + FastShl<Output = S>,
+        {
+            type Output = Self;
+
             #[inline(always)]
-            fn fast_mul(self, rhs: Self) -> Self {
-                Self { $( $field: self.$field.fast_mul(rhs.$field) ),+ }
+            fn fast_shl<R: ShiftAmount>(self, rhs: R) -> Self {
+                Self { $( $field: self.$field.fast_shl(rhs) ),+ }
             }
+
             #[inline(always)]
-            fn fast_div(self, rhs: Self) -> Self {
-                Self { $( $field: self.$field.fast_div(rhs.$field) ),+ }
+            fn try_fast_shl<R: ShiftAmount>(self, rhs: R) -> Option<Self> {
+                Some(Self { $( $field: self.$field.try_fast_shl(rhs)? ),+ })
             }
+        }
+
+        impl<S> FastShr for $t<S>
+        where
+            S: Copy + FastShr<Output = S>,
+        {
+            type Output = Self;
+
             #[inline(always)]
-            fn fast_rem(self, rhs: Self) -> Self {
-                Self { $( $field: self.$field.fast_rem(rhs.$field) ),+ }
+            fn fast_shr<R: ShiftAmount>(self, rhs: R) -> Self {
+                Self { $( $field: self.$field.fast_shr(rhs) ),+ }
             }
+
             #[inline(always)]
-            fn fast_shl(self, rhs: u32) -> Self {
-                Self { $( $field: self.$field.fast_shl(rhs) ),+ }
+            fn try_fast_shr<R: ShiftAmount>(self, rhs: R) -> Option<Self> {
+                Some(Self { $( $field: self.$field.try_fast_shr(rhs)? ),+ })
             }
+        }
+
+        impl_policy_for_vek!($t { $($field),+ }; WrappingMath:
+            wrapping_add, wrapping_sub, wrapping_mul, wrapping_div, wrapping_rem; wrapping_shl, wrapping_shr; wrapping_neg);
+        impl_policy_for_vek!($t { $($field),+ }; SaturatingMath:
+            saturating_add, saturating_sub, saturating_mul, saturating_div, saturating_rem; saturating_shl, saturating_shr; saturating_neg);
+
+        impl<S> FastNeg for $t<S>
+        where
+            S: Copy + FastNeg,
+        {
             #[inline(always)]
-            fn fast_shr(self, rhs: u32) -> Self {
-                Self { $( $field: self.$field.fast_shr(rhs) ),+ }
+            fn fast_neg(self) -> Self {
+                Self { $( $field: self.$field.fast_neg() ),+ }
+            }
+
+            #[inline(always)]
+            fn try_fast_neg(self) -> Option<Self> {
+                Some(Self { $( $field: self.$field.try_fast_neg()? ),+ })
+            }
+
+            #[inline(always)]
+            fn fast_neg_with(self, flags: FloatFlags) -> Self {
+                Self { $( $field: self.$field.fast_neg_with(flags) ),+ }
+            }
+
+            #[inline(always)]
+            fn try_fast_neg_with(self, flags: FloatFlags) -> Option<Self> {
+                Some(Self { $( $field: self.$field.try_fast_neg_with(flags)? ),+ })
+            }
+        }
+    };
+}
+
+#[cfg(feature = "std")]
+macro_rules! impl_fast_op_for_vek {
+    ($t:ident { $($field:ident),+ }; $trait_:ident::$fast:ident, $try_fast:ident, $fast_with:ident, $try_fast_with:ident) => {
+        impl<S> $trait_ for $t<S>
+        where
+            S: Copy + $trait_<Output = S>,
+        {
+            type Output = Self;
+
+            #[inline(always)]
+            fn $fast(self, rhs: Self) -> Self {
+                Self { $( $field: self.$field.$fast(rhs.$field) ),+ }
+            }
+
+            #[inline(always)]
+            fn $try_fast(self, rhs: Self) -> Option<Self> {
+                Some(Self { $( $field: self.$field.$try_fast(rhs.$field)? ),+ })
+            }
+
+            #[inline(always)]
+            fn $fast_with(self, rhs: Self, flags: FloatFlags) -> Self {
+                Self { $( $field: self.$field.$fast_with(rhs.$field, flags) ),+ }
+            }
+
+            #[inline(always)]
+            fn $try_fast_with(self, rhs: Self, flags: FloatFlags) -> Option<Self> {
+                Some(Self { $( $field: self.$field.$try_fast_with(rhs.$field, flags)? ),+ })
+            }
+        }
+
+        // scalar is broadcast to all elements, same as std ops of vek
+        impl<S> $trait_<S> for $t<S>
+        where
+            S: Copy + $trait_<Output = S>,
+        {
+            type Output = Self;
+
+            #[inline(always)] fn $fast(self, rhs: S) -> Self { self.$fast(Self::from(rhs)) }
+
+            #[inline(always)] fn $try_fast(self, rhs: S) -> Option<Self> { self.$try_fast(Self::from(rhs)) }
+
+            #[inline(always)] fn $fast_with(self, rhs: S, flags: FloatFlags) -> Self { self.$fast_with(Self::from(rhs), flags) }
+
+            #[inline(always)]
+            fn $try_fast_with(self, rhs: S, flags: FloatFlags) -> Option<Self> {
+                self.$try_fast_with(Self::from(rhs), flags)
             }
         }
+
+        impl_fast_op_for_refs!(<S> $t<S>, $t<S> where [S: Copy + $trait_<Output = S>]; $trait_::$fast, $try_fast, $fast_with, $try_fast_with);
+        impl_fast_op_for_refs!(<S> $t<S>, S where [S: Copy + $trait_<Output = S>]; $trait_::$fast, $try_fast, $fast_with, $try_fast_with);
+    };
+    // can't be generic over the scalar (coherence), so same primitive types as vek has
+    (@scalar_lhs $t:ident; $trait_:ident::$fast:ident, $try_fast:ident, $fast_with:ident, $try_fast_with:ident; $($s:ty),*) => {
+        $(
+            impl $trait_<$t<$s>> for $s {
+                type Output = $t<$s>;
+
+                #[inline(always)] fn $fast(self, rhs: $t<$s>) -> $t<$s> { $t::from(self).$fast(rhs) }
+
+                #[inline(always)] fn $try_fast(self, rhs: $t<$s>) -> Option<$t<$s>> { $t::from(self).$try_fast(rhs) }
+
+                #[inline(always)]
+                fn $fast_with(self, rhs: $t<$s>, flags: FloatFlags) -> $t<$s> {
+                    $t::from(self).$fast_with(rhs, flags)
+                }
+
+                #[inline(always)]
+                fn $try_fast_with(self, rhs: $t<$s>, flags: FloatFlags) -> Option<$t<$s>> {
+                    $t::from(self).$try_fast_with(rhs, flags)
+                }
+            }
+        )*
+    };
+}
+
+// element-wise, vector with vector only
+#[cfg(feature = "std")]
+macro_rules! impl_policy_for_vek {
+    ($t:ident $fields:tt; $trait_:ident: $($op:ident),+; $($shift:ident),+; $neg:ident) => {
+        impl<S: Copy + $trait_> $trait_ for $t<S> {
+            $( impl_policy_for_vek!(@op $op $fields); )+
+            $( impl_policy_for_vek!(@shift $shift $fields); )+
+            impl_policy_for_vek!(@neg $neg $fields);
+        }
+    };
+    (@op $op:ident { $($field:ident),+ }) => {
+        #[inline(always)]
+        fn $op(self, rhs: Self) -> Self {
+            Self { $( $field: self.$field.$op(rhs.$field) ),+ }
+        }
+    };
+    (@shift $shift:ident { $($field:ident),+ }) => {
+        #[inline(always)]
+        fn $shift<R: ShiftAmount>(self, rhs: R) -> Self {
+            Self { $( $field: self.$field.$shift(rhs) ),+ }
+        }
+    };
+    (@neg $neg:ident { $($field:ident),+ }) => {
+        #[inline(always)]
+        fn $neg(self) -> Self {
+            Self { $( $field: self.$field.$neg() ),+ }
+        }
     };
 }
 
-use qvek::vek::{Extent2, Extent3, Rgb, Rgba, Vec2, Vec3, Vec4};
+// qvek pulls vek with std
+#[cfg(feature = "std")]
+mod vek {
+    use super::*;
+    use qvek::vek::{Extent2, Extent3, Rgb, Rgba, Vec2, Vec3, Vec4};
 
-// Coordinate Vectors
-impl_fast_math_for_vek!(Vec2 { x, y });
-impl_fast_math_for_vek!(Vec3 { x, y, z });
-impl_fast_math_for_vek!(Vec4 { x, y, z, w });
+    // Coordinate Vectors
+    impl_fast_math_for_vek!(Vec2 { x, y } for i8, u8, i16, u16, i32, u32, i64, u64, f32, f64);
+    impl_fast_math_for_vek!(Vec3 { x, y, z } for i8, u8, i16, u16, i32, u32, i64, u64, f32, f64);
+    impl_fast_math_for_vek!(Vec4 { x, y, z, w } for i8, u8, i16, u16, i32, u32, i64, u64, f32, f64);
 
-// Color Types
-impl_fast_math_for_vek!(Rgb { r, g, b });
-impl_fast_math_for_vek!(Rgba { r, g, b, a });
+    // Color Types
+    impl_fast_math_for_vek!(Rgb { r, g, b } for i8, u8, i16, u16, i32, u32, i64, u64, f32, f64);
+    impl_fast_math_for_vek!(Rgba { r, g, b, a } for i8, u8, i16, u16, i32, u32, i64, u64, f32, f64);
 
-// Extent/Size Types
-impl_fast_math_for_vek!(Extent2 { w, h });
-impl_fast_math_for_vek!(Extent3 { w, h, d });
+    // Extent/Size Types
+    impl_fast_math_for_vek!(Extent2 { w, h } for i8, u8, i16, u16, i32, u32, i64, u64, f32, f64);
+    impl_fast_math_for_vek!(Extent3 { w, h, d } for i8, u8, i16, u16, i32, u32, i64, u64, f32, f64);
+}